@@ -0,0 +1,155 @@
+struct Charge {
+  pos: vec3<f32>;
+  q: f32;
+};
+
+[[block]]
+struct Charges {
+  data: [[stride(16)]] array<Charge>;
+};
+
+// Smallest `r^2` the point charges are evaluated at, so that a grid node
+// right on a charge gets a finite potential and no field instead of NaN. See
+// `CHARGE_SOFTENING` in `scene.rs`.
+let CHARGE_SOFTENING: f32 = 1e-6;
+
+// Field of a point charge in scene units, `q / r^2`.
+fn get_charge(p: vec3<f32>, charge: Charge) -> vec3<f32> {
+  let pc = p - charge.pos;
+  let r2 = max(dot(pc, pc), CHARGE_SOFTENING);
+  return pc * (charge.q / pow(r2, 1.5));
+}
+
+// Potential `get_charge` is the negative gradient of.
+fn get_charge_potential(p: vec3<f32>, charge: Charge) -> f32 {
+  let pc = p - charge.pos;
+  return charge.q / sqrt(max(dot(pc, pc), CHARGE_SOFTENING));
+}
+
+// Share of the field of `charge` that the hybrid field mode evaluates
+// analytically, one up to half of `cutoff` from it and easing to zero at
+// `cutoff` with a smootherstep. The rest is baked into the field texture.
+fn near_weight(p: vec3<f32>, charge: Charge, cutoff: f32) -> f32 {
+  let t = clamp(2. * distance(p, charge.pos) / cutoff - 1., 0., 1.);
+  return 1. - t * t * t * (t * (t * 6. - 15.) + 10.);
+}
+
+// Extended and multipole sources, see `ChargeDistribution` in `scene.rs` for
+// how the members are used by each kind.
+struct Distribution {
+  origin: vec3<f32>;
+  kind: u32;
+  axis: vec3<f32>;
+  strength: f32;
+  radius: f32;
+};
+
+[[block]]
+struct Distributions {
+  data: [[stride(48)]] array<Distribution>;
+};
+
+let DISTRIBUTION_LINE: u32 = 0u;
+let DISTRIBUTION_RING: u32 = 1u;
+let DISTRIBUTION_DISC: u32 = 2u;
+let DISTRIBUTION_PLANE: u32 = 3u;
+let DISTRIBUTION_DIPOLE: u32 = 4u;
+let DISTRIBUTION_QUADRUPOLE: u32 = 5u;
+
+let DISTRIBUTION_SOFTENING: f32 = 1.0e-4;
+let DISC_RINGS: u32 = 32u;
+let PI: f32 = 3.14159265;
+
+// Complete elliptic integrals `(K(m), E(m))` by the arithmetic-geometric mean.
+fn elliptic_integrals(m: f32) -> vec2<f32> {
+  var a = 1.;
+  var b = sqrt(1. - m);
+  var sum = 0.5 * m;
+  var weight = 0.5;
+  for (var i = 0; i < 8; i = i + 1) {
+    let c = 0.5 * (a - b);
+    weight = weight * 2.;
+    sum = sum + weight * c * c;
+    let mean = 0.5 * (a + b);
+    b = sqrt(a * b);
+    a = mean;
+  }
+  let k = 0.5 * PI / a;
+  return vec2<f32>(k, k * (1. - sum));
+}
+
+// Field in `xyz` and potential in `w` of a thin ring.
+fn get_ring(p: vec3<f32>, center: vec3<f32>, normal: vec3<f32>, radius: f32, q: f32) -> vec4<f32> {
+  let d = p - center;
+  let z = dot(d, normal);
+  let perp = d - normal * z;
+  let rho = max(length(perp), 1.0e-6);
+  let z2 = z * z + DISTRIBUTION_SOFTENING;
+  let plus = (radius + rho) * (radius + rho) + z2;
+  let minus = (radius - rho) * (radius - rho) + z2;
+  let ke = elliptic_integrals(4. * radius * rho / plus);
+  let scale = q / (PI * sqrt(plus));
+  let radial = scale / rho * (ke.x - (radius * radius - rho * rho + z2) / minus * ke.y);
+  let axial = 2. * scale * z * ke.y / minus;
+  return vec4<f32>(perp / rho * radial + normal * axial, 2. * scale * ke.x);
+}
+
+// Field in `xyz` and potential in `w` of a distribution.
+fn get_distribution(p: vec3<f32>, d: Distribution) -> vec4<f32> {
+  if (d.kind == DISTRIBUTION_LINE) {
+    let len = length(d.axis);
+    let dir = d.axis / len;
+    let pa = p - d.origin;
+    let x = dot(pa, dir);
+    let perp = pa - dir * x;
+    let rho2 = dot(perp, perp) + DISTRIBUTION_SOFTENING;
+    let r1 = sqrt(rho2 + x * x);
+    let r2 = sqrt(rho2 + (len - x) * (len - x));
+    let lambda = d.strength / len;
+    let field = perp / rho2 * ((len - x) / r2 + x / r1) + dir * (1. / r2 - 1. / r1);
+    return vec4<f32>(field, log((r1 + r2 + len) / (r1 + r2 - len))) * lambda;
+  } elseif (d.kind == DISTRIBUTION_RING) {
+    return get_ring(p, d.origin, d.axis, d.radius, d.strength);
+  } elseif (d.kind == DISTRIBUTION_DISC) {
+    var res = vec4<f32>(0.);
+    let rings = f32(DISC_RINGS);
+    for (var i = 0u; i < DISC_RINGS; i = i + 1u) {
+      let r = d.radius * sqrt((f32(i) + 0.5) / rings);
+      res = res + get_ring(p, d.origin, d.axis, r, d.strength / rings);
+    }
+    return res;
+  } elseif (d.kind == DISTRIBUTION_PLANE) {
+    let h = dot(p - d.origin, d.axis);
+    let scale = 2. * PI * d.strength;
+    return vec4<f32>(d.axis * scale * sign(h), -scale * abs(h));
+  } elseif (d.kind == DISTRIBUTION_DIPOLE) {
+    let r = p - d.origin;
+    let r2 = dot(r, r) + DISTRIBUTION_SOFTENING;
+    let r3 = pow(r2, 1.5);
+    let mr = dot(d.axis, r);
+    return vec4<f32>((3. * mr / r2 * r - d.axis) / r3, mr / r3);
+  }
+  // Quadrupole.
+  let r = p - d.origin;
+  let r2 = dot(r, r) + DISTRIBUTION_SOFTENING;
+  let r5 = pow(r2, 2.5);
+  let a = dot(d.axis, r);
+  return vec4<f32>(
+    ((5. * a * a / r2 - 1.) * r - 2. * a * d.axis) * (0.75 * d.strength / r5),
+    d.strength * (3. * a * a - r2) / (4. * r5),
+  );
+}
+
+// Refinement brick of the field, `BRICK_SIZE` nodes per axis starting at
+// `min`, see `FieldBaker` in `bake.rs`.
+struct Brick {
+  min: vec3<f32>;
+  spacing: f32;
+};
+
+[[block]]
+struct Bricks {
+  data: [[stride(16)]] array<Brick>;
+};
+
+let BRICK_SIZE: u32 = 16u;
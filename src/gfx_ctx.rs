@@ -1,21 +1,52 @@
+mod bake;
+mod diagnostics;
+mod emitter;
+mod gauss;
+mod isosurface;
 mod line;
+mod nbody;
+mod pic;
+mod poisson;
+mod probe;
 
+use std::num::NonZeroU32;
 use std::ops::Range;
 
 use bytemuck::{Pod, Zeroable};
-use glam::{vec3, Vec3, Vec4};
-use rand::Rng;
+use glam::{Vec3, Vec4};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use raw_window_handle::HasRawWindowHandle;
 use wgpu::util::DeviceExt;
 
+pub use diagnostics::Diagnostics;
+pub use gauss::{GaussFlux, GaussSurface};
+pub use probe::{Probe, ProbeSample};
+
 use crate::{
     camera::{Camera, CameraUniform},
-    gfx_ctx::line::draw_lines_command,
+    gfx_ctx::{
+        bake::FieldBaker,
+        diagnostics::ParticleReduction,
+        emitter::Emitters,
+        isosurface::Isosurface,
+        line::draw_lines_command,
+        nbody::BarnesHut,
+        pic::SpaceCharge,
+        poisson::{charge_density, PoissonSolver},
+        probe::FieldProbe,
+    },
+    scene::{
+        get_field, get_magnetic_field, get_potential, Boundaries, Boundary, Charge,
+        ChargeDistribution, Dielectric, Domain, Electrode, Emitter, MagneticSource, Scene, Units,
+    },
 };
 
 const WORKGROUP_SIZE: u32 = 256;
 pub fn dispatch_size(len: u32) -> u32 {
-    let subgroup_size = WORKGROUP_SIZE;
+    workgroup_count(len, WORKGROUP_SIZE)
+}
+
+pub fn workgroup_count(len: u32, subgroup_size: u32) -> u32 {
     let padded_size = (subgroup_size - len % subgroup_size) % subgroup_size;
     (len + padded_size) / subgroup_size
 }
@@ -69,86 +100,78 @@ pub fn create_depth_texture(
     texture.create_view(&wgpu::TextureViewDescriptor::default())
 }
 
-#[derive(Clone, Copy, Debug)]
-struct Charge {
-    q: f32,
-    pos: Vec3,
-}
-
-impl Charge {
-    fn new_rand(rng: &mut impl Rng) -> Self {
-        let q_range = 0.3;
-        Self {
-            q: rng.gen_range(-q_range..q_range),
-            pos: Vec3::from([0., 0., 0.].map(|_| rng.gen_range(-0.5..0.5))),
-        }
-    }
-}
-
-fn get_charge(pos: Vec3, charge: Charge) -> Vec3 {
-    let pc = pos - charge.pos;
-    let r2 = pc.dot(pc);
-    pc * (charge.q / r2.powf(1.5) + 1.0e-3)
-}
-
-fn get_field(p: Vec3, charges: &[Charge]) -> Vec3 {
-    charges
-        .iter()
-        .fold(Vec3::ZERO, |acc, &q| acc + get_charge(p, q))
+fn create_field_texture(
+    device: &wgpu::Device,
+    label: &str,
+    [width, height, depth]: [u32; 3],
+    usage: wgpu::TextureUsages,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage,
+    })
 }
 
-fn get_field_texture(
-    device: &wgpu::Device,
+/// Evaluates `field` on the CPU for every voxel and uploads it.
+fn write_field_texture(
     queue: &wgpu::Queue,
-    width: u32,
-    height: u32,
-    depth: u32,
-) -> wgpu::TextureView {
-    let mut rng = rand::thread_rng();
-    let charges: Vec<Charge> = (0..6).map(|_| Charge::new_rand(&mut rng)).collect();
+    texture: &wgpu::Texture,
+    domain: &Domain,
+    field: impl Fn(Vec3) -> Vec3,
+) {
+    let [width, height, depth] = domain.resolution;
     let texture_data: Vec<Vec4> = (0..width * height * depth)
         .map(|id| {
-            let i = id as f32;
-            let (width, height, depth) = (width as f32, height as f32, depth as f32);
-            let [x, y, z] = [i % width, (i / width) % height, i / (width * height)];
-
-            let p = vec3(x, y, z) / vec3(width, height, depth) * 2.0 - 1.0;
+            let [x, y, z] = [id % width, (id / width) % height, id / (width * height)];
 
-            get_field(p, &charges).extend(1.)
+            field(domain.node_position([x, y, z])).extend(1.)
         })
         .collect();
-    let tex = device.create_texture_with_data(
-        queue,
-        &wgpu::TextureDescriptor {
-            label: Some("Field Texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: depth,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D3,
-            format: wgpu::TextureFormat::Rgba32Float,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
         },
         bytemuck::cast_slice(&texture_data),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(width * std::mem::size_of::<Vec4>() as u32),
+            rows_per_image: NonZeroU32::new(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        },
     );
-    tex.create_view(&Default::default())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_particles_command(
     device: &wgpu::Device,
     sample_count: u32,
     format: wgpu::TextureFormat,
     camera_bind_group_layout: &wgpu::BindGroupLayout,
     camera_bind_group: &wgpu::BindGroup,
+    coloring_bind_group_layout: &wgpu::BindGroupLayout,
+    coloring_bind_group: &wgpu::BindGroup,
     particle_buffer: &wgpu::Buffer,
     particles_num: u32,
 ) -> wgpu::RenderBundle {
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Particle Pipeline Descriptor"),
-        bind_group_layouts: &[camera_bind_group_layout],
+        bind_group_layouts: &[camera_bind_group_layout, coloring_bind_group_layout],
         push_constant_ranges: &[],
     });
 
@@ -207,6 +230,7 @@ fn draw_particles_command(
     encoder.set_pipeline(&draw_particles_pipeline);
     encoder.set_vertex_buffer(0, particle_buffer.slice(..));
     encoder.set_bind_group(0, camera_bind_group, &[]);
+    encoder.set_bind_group(1, coloring_bind_group, &[]);
     encoder.draw(0..particles_num, 0..1);
     encoder.finish(&wgpu::RenderBundleDescriptor {
         label: Some("Draw Particles Bundle"),
@@ -219,19 +243,23 @@ struct Particle {
     pos: Vec4,
     vel: Vec4,
     lifetime: f32,
-    _padding: [f32; 3],
+    charge: f32,
+    mass: f32,
+    _padding: f32,
 }
 
 #[allow(dead_code)]
 impl Particle {
     const VERTEX_FORMAT: [wgpu::VertexAttribute; 3] =
         wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x4];
-    fn new(pos: Vec4, vel: Vec4, lifetime: f32) -> Self {
+    fn new(pos: Vec4, vel: Vec4, lifetime: f32, species: ParticleSpecies) -> Self {
         Self {
             pos,
             vel,
             lifetime,
-            _padding: [0.; 3],
+            charge: species.charge,
+            mass: species.mass,
+            _padding: 0.,
         }
     }
 
@@ -239,6 +267,7 @@ impl Particle {
         pos_range: Range<f32>,
         vel_range: Range<f32>,
         life_range: Range<f32>,
+        species: ParticleSpecies,
         rng: &mut impl Rng,
     ) -> Self {
         use std::array::from_fn;
@@ -246,16 +275,280 @@ impl Particle {
             Vec4::from(from_fn(|_| rng.gen_range(pos_range.clone()))),
             Vec4::from(from_fn(|_| rng.gen_range(vel_range.clone()))),
             rng.gen_range(life_range),
+            species,
         )
     }
 }
 
+/// Charge and mass of the particles pushed by the massive integrators.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct ParticleSpecies {
+    pub charge: f32,
+    pub mass: f32,
+}
+
+impl Default for ParticleSpecies {
+    fn default() -> Self {
+        Self {
+            charge: 1.,
+            mass: 1.,
+        }
+    }
+}
+
+/// What the particles are coloured by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticleColoring {
+    /// Octant of the particle.
+    Position,
+    /// Electrostatic potential at the particle.
+    Potential,
+}
+
+impl ParticleColoring {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Position => Self::Potential,
+            Self::Potential => Self::Position,
+        }
+    }
+}
+
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct ColoringUniform {
+    mode: u32,
+    potential_scale: f32,
+}
+
+/// What happens to a particle crossing a face of the simulation domain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticleBoundary {
+    /// Spawned anew inside the domain.
+    Respawn,
+    /// Specular reflection, the normal velocity flips.
+    Reflect,
+    /// Wraps to the opposite face, keeping its velocity.
+    Periodic,
+    /// Deactivated, it is no longer drawn nor integrated until no face
+    /// absorbs any more.
+    Absorb,
+}
+
+impl ParticleBoundary {
+    pub const ALL: [Self; 4] = [Self::Respawn, Self::Reflect, Self::Periodic, Self::Absorb];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Where dead particles are brought back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Seeding {
+    /// From the scene emitters, uniformly in the domain without any.
+    Emitters,
+    /// Field lines starting on small spheres around the charges, as many per
+    /// charge as its share of the total `|q|`. Lines of positive charges are
+    /// traced forward and the ones of negative charges backward.
+    Flux,
+}
+
+impl Seeding {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Emitters => Self::Flux,
+            Self::Flux => Self::Emitters,
+        }
+    }
+}
+
+/// Particle boundaries of the `-x, +x, -y, +y, -z, +z` faces.
+pub type ParticleBoundaries = [ParticleBoundary; 6];
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct ParticleBoundaryUniform {
+    lower: [u32; 3],
+    _padding0: u32,
+    upper: [u32; 3],
+    _padding1: u32,
+}
+
+impl From<ParticleBoundaries> for ParticleBoundaryUniform {
+    fn from(boundaries: ParticleBoundaries) -> Self {
+        let [lower_x, upper_x, lower_y, upper_y, lower_z, upper_z] = boundaries.map(|b| b as u32);
+        Self {
+            lower: [lower_x, lower_y, lower_z],
+            _padding0: 0,
+            upper: [upper_x, upper_y, upper_z],
+            _padding1: 0,
+        }
+    }
+}
+
+/// Time integration scheme used by [`Context::simulate`].
+///
+/// `Euler`, `Midpoint` and `Rk4` trace field lines, `dx/dt = E(x)`.
+/// `VelocityVerlet` and `Boris` push massive charged particles,
+/// `dv/dt = q/m (E + v x B)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Integrator {
+    Euler,
+    Midpoint,
+    Rk4,
+    VelocityVerlet,
+    Boris,
+}
+
+impl Integrator {
+    pub const ALL: [Self; 5] = [
+        Self::Euler,
+        Self::Midpoint,
+        Self::Rk4,
+        Self::VelocityVerlet,
+        Self::Boris,
+    ];
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            Self::Euler => "integrate",
+            Self::Midpoint => "integrate_midpoint",
+            Self::Rk4 => "integrate_rk4",
+            Self::VelocityVerlet => "integrate_verlet",
+            Self::Boris => "integrate_boris",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// How the particle kernels evaluate the electric field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldMode {
+    /// Trilinear sample of the baked field texture. Fast, but smeared out
+    /// near the charges.
+    Texture,
+    /// Sum of the Coulomb contributions of every charge, per particle.
+    Analytic,
+    /// The charges within a few grid cells of the particle analytically and
+    /// the smooth rest of the field from a texture baked without them, for
+    /// the strongest charges, those with refinement bricks. Only
+    /// differs from [`FieldMode::Texture`] with [`FieldSolver::Coulomb`], where
+    /// the equipotential surfaces are hidden.
+    Hybrid,
+}
+
+impl FieldMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Texture => Self::Analytic,
+            Self::Analytic => Self::Hybrid,
+            Self::Hybrid => Self::Texture,
+        }
+    }
+}
+
+/// What produces the field texture sampled in [`FieldMode::Texture`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldSolver {
+    /// Coulomb superposition of the charge list.
+    Coulomb,
+    /// Poisson solve of the charge density volume.
+    Poisson,
+    /// Particle-in-cell, the Poisson solve also takes the charge of the
+    /// particles and is repeated every step.
+    Pic,
+}
+
+impl FieldSolver {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Coulomb => Self::Poisson,
+            Self::Poisson => Self::Pic,
+            Self::Pic => Self::Coulomb,
+        }
+    }
+}
+
+/// Forces between the particles themselves, on top of the field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interaction {
+    /// Particles only feel the field of the scene.
+    None,
+    /// Pairwise Coulomb forces through a Barnes–Hut octree.
+    BarnesHut,
+}
+
+impl Interaction {
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::BarnesHut,
+            Self::BarnesHut => Self::None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct FieldParams {
+    mode: u32,
+    charge_count: u32,
+    interaction: u32,
+    distribution_count: u32,
+    field_scale: f32,
+    potential_scale: f32,
+    display_scale: f32,
+    charge_scale: f32,
+    length_scale: f32,
+    brick_count: u32,
+    near_cutoff: f32,
+    near_count: u32,
+    domain_min: [f32; 3],
+    _padding1: f32,
+    domain_max: [f32; 3],
+    _padding2: f32,
+}
+
+impl FieldParams {
+    fn set_units(&mut self, units: &Units) {
+        self.field_scale = units.field_scale();
+        self.potential_scale = units.potential_scale();
+        self.display_scale = units.display_scale;
+        self.charge_scale = units.charge_unit.coulombs();
+        self.length_scale = units.length_scale;
+    }
+}
+
+/// Electrodes with their potential converted from volts to scene units.
+fn scene_electrodes(electrodes: &[Electrode], units: &Units) -> Vec<Electrode> {
+    electrodes
+        .iter()
+        .map(|&electrode| Electrode {
+            potential: electrode.potential / units.potential_scale(),
+            ..electrode
+        })
+        .collect()
+}
+
+/// Boundaries with their potential converted from volts to scene units.
+fn scene_boundaries(boundaries: &Boundaries, units: &Units) -> Boundaries {
+    boundaries.map(|boundary| match boundary {
+        Boundary::Dirichlet(potential) => Boundary::Dirichlet(potential / units.potential_scale()),
+        Boundary::Neumann => Boundary::Neumann,
+    })
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 struct SharedUniform {
     dt: f32,
     time: f32,
     seed: u32,
+    _padding: u32,
 }
 
 pub struct Context {
@@ -277,26 +570,89 @@ pub struct Context {
     camera_buffer: wgpu::Buffer,
 
     draw_particles_command: wgpu::RenderBundle,
+    particle_coloring: ParticleColoring,
+    coloring: ColoringUniform,
+    coloring_buffer: wgpu::Buffer,
+    draw_isosurface_command: wgpu::RenderBundle,
     particle_num: u32,
     _particle_buffer: wgpu::Buffer,
     particle_bind_group: wgpu::BindGroup,
 
-    rand_uniform: wgpu::Buffer,
-    _rand_uniform_binding: wgpu::BindGroup,
-
-    integrate_pipeline: wgpu::ComputePipeline,
+    /// Every random number of the particles derives from the scene seed
+    /// through this generator.
+    rng: StdRng,
+
+    shared_uniform: SharedUniform,
+    time_buffer: wgpu::Buffer,
+    species: ParticleSpecies,
+    species_buffer: wgpu::Buffer,
+    particle_boundaries: ParticleBoundaries,
+    particle_boundary_buffer: wgpu::Buffer,
+    emitters: Emitters,
+    emit_pipeline: wgpu::ComputePipeline,
+    reseed_pipeline: wgpu::ComputePipeline,
+    respawn_parked_pipeline: wgpu::ComputePipeline,
+    respawn_absorbed_pipeline: wgpu::ComputePipeline,
+    respawn_electrode_hits_pipeline: wgpu::ComputePipeline,
+    time_bind_group: wgpu::BindGroup,
+
+    pub integrator: Integrator,
+    integrate_pipelines: [wgpu::ComputePipeline; Integrator::ALL.len()],
     simulation_pipeline: wgpu::ComputePipeline,
 
+    field_baker: FieldBaker,
+    units: Units,
+    charges: Vec<Charge>,
+    distributions: Vec<ChargeDistribution>,
+    charges_changed: bool,
+    field_solver: FieldSolver,
+    field_mode: FieldMode,
+    interaction: Interaction,
+    poisson: PoissonSolver,
+    domain: Domain,
+    poisson_sweeps_left: u32,
+    space_charge: SpaceCharge,
+    barnes_hut: BarnesHut,
+    isosurface: Isosurface,
+    equipotentials: Vec<f32>,
+    isosurface_outdated: bool,
+    /// Charge of the particles relative to the scene charges in
+    /// [`FieldSolver::Pic`] and [`Interaction::BarnesHut`], every simulated
+    /// particle stands for this many real ones.
+    pub macro_weight: f32,
+    field_params: FieldParams,
+    field_params_buffer: wgpu::Buffer,
+    magnetic_texture: wgpu::Texture,
     field_texture_binding: wgpu::BindGroup,
+
+    /// Reduces the particles into [`Diagnostics`] after every step, collected
+    /// with [`Context::poll_diagnostics`].
+    pub record_diagnostics: bool,
+    particle_reduction: ParticleReduction,
+    field_probe: FieldProbe,
 }
 
 impl Context {
     const MSAA_SAMPLE_COUNT: u32 = 4;
+    /// Red-black sweeps run after the Poisson problem changes.
+    const POISSON_SWEEPS: u32 = 512;
+    const POISSON_SWEEPS_PER_STEP: u32 = 16;
+    /// Inverse of the potential mapped to the saturated ends of the colour
+    /// map, roughly, in the dimensionless `q / r` of
+    /// [`Units::potential_scale`].
+    const POTENTIAL_COLOR_SCALE: f32 = 0.4;
+    /// Default [`Context::macro_weight`], a million unit charges add up to a
+    /// few scene charges.
+    const MACRO_WEIGHT: f32 = 1.0e-6;
+    /// Radius around the charges evaluated analytically in
+    /// [`FieldMode::Hybrid`], in field grid cells.
+    const NEAR_CUTOFF_CELLS: f32 = 4.;
     pub async fn new(
         window: &impl HasRawWindowHandle,
         width: u32,
         height: u32,
         camera: Camera,
+        scene: &Scene,
     ) -> Self {
         let instance = wgpu::Instance::new(wgpu::Backends::all());
 
@@ -374,8 +730,10 @@ impl Context {
             label: Some("camera_bind_group"),
         });
 
+        let domain = scene.domain;
         let draw_lines_command = draw_lines_command(
             &device,
+            &domain,
             Self::MSAA_SAMPLE_COUNT,
             format,
             &camera_bind_group_layout,
@@ -411,17 +769,27 @@ impl Context {
                 resource: particle_buffer.as_entire_binding(),
             }],
         });
-        let rand_uniform = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Rand Uniform"),
-            size: std::mem::size_of::<f32>() as _,
+        let sim_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("simulation.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("charge.wgsl"), include_str!("simulation.wgsl")).into(),
+            ),
+        });
+        let coloring = ColoringUniform {
+            mode: ParticleColoring::Position as _,
+            potential_scale: Self::POTENTIAL_COLOR_SCALE / scene.units.potential_scale(),
+        };
+        let coloring_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Coloring"),
+            contents: bytemuck::bytes_of(&coloring),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
         });
-        let rand_uniform_layout =
+        let coloring_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Coloring Bind Group Layout"),
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -429,58 +797,100 @@ impl Context {
                     },
                     count: None,
                 }],
-                label: Some("camera_bind_group_layout"),
             });
-        let rand_uniform_binding = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &&rand_uniform_layout,
+        let coloring_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Coloring Bind Group"),
+            layout: &coloring_bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
-                resource: rand_uniform.as_entire_binding(),
+                resource: coloring_buffer.as_entire_binding(),
             }],
         });
-        let sim_shader = device.create_shader_module(&wgpu::include_wgsl!("simulation.wgsl"));
-        let fill_shader = {
-            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Fill Pipeline Layout"),
-                bind_group_layouts: &[&particle_bind_group_layout, &rand_uniform_layout],
-                push_constant_ranges: &[],
-            });
-            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("Fill Pipeline"),
-                layout: Some(&pipeline_layout),
-                module: &sim_shader,
-                entry_point: "fill",
-            })
-        };
-        {
-            let mut encoder = device.create_command_encoder(&Default::default());
-            let mut cpass = encoder.begin_compute_pass(&Default::default());
-            cpass.set_pipeline(&fill_shader);
-            cpass.set_bind_group(0, &particle_bind_group, &[]);
-            cpass.set_bind_group(1, &rand_uniform_binding, &[]);
-            cpass.dispatch(dispatch_size(particle_num), 1, 1);
-            drop(cpass);
-            queue.submit(Some(encoder.finish()));
-        }
-
         let draw_particles_command = draw_particles_command(
             &device,
             Self::MSAA_SAMPLE_COUNT,
             format,
             &camera_bind_group_layout,
             &camera_bind_group,
+            &coloring_bind_group_layout,
+            &coloring_bind_group,
             &particle_buffer,
             particle_num,
         );
 
-        let [width, height, depth] = [0, 0, 0].map(|_| 32 * 2);
-        let field_texture = get_field_texture(&device, &queue, width, height, depth);
+        let field_texture = create_field_texture(
+            &device,
+            "Field Texture",
+            domain.resolution,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        )
+        .create_view(&Default::default());
+        let mut field_baker = FieldBaker::new(&device, &field_texture, &domain);
+        field_baker.set_charges(&queue, &scene.charges);
+        field_baker.set_distributions(&queue, &scene.distributions);
+        let space_charge = SpaceCharge::new(&device, &particle_buffer, particle_num, &domain);
+        let barnes_hut = BarnesHut::new(&device, &particle_buffer, particle_num, &domain);
+        let particle_reduction = ParticleReduction::new(&device, &particle_buffer, particle_num);
+        let isosurface = Isosurface::new(&device, &field_texture, &domain);
+        let draw_isosurface_command = isosurface.draw_command(
+            &device,
+            Self::MSAA_SAMPLE_COUNT,
+            format,
+            &camera_bind_group_layout,
+            &camera_bind_group,
+        );
+        let mut poisson =
+            PoissonSolver::new(&device, &field_texture, space_charge.buffer(), &domain);
+        poisson.set_density(
+            &queue,
+            &charge_density(&scene.charges, &scene.distributions, &domain),
+        );
+        poisson.set_electrodes(&queue, &scene_electrodes(&scene.electrodes, &scene.units));
+        poisson.set_dielectrics(&queue, &scene.dielectrics);
+        poisson.set_boundaries(&queue, &scene_boundaries(&scene.boundaries, &scene.units));
+        {
+            let mut encoder = device.create_command_encoder(&Default::default());
+            field_baker.bake(&mut encoder);
+            queue.submit(Some(encoder.finish()));
+        }
+        let magnetic_texture = create_field_texture(
+            &device,
+            "Magnetic Field Texture",
+            domain.resolution,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        );
+        write_field_texture(&queue, &magnetic_texture, &domain, |p| {
+            get_magnetic_field(p, &scene.magnets)
+        });
+        let mut field_params = FieldParams {
+            mode: FieldMode::Texture as _,
+            charge_count: scene.charges.len().min(FieldBaker::MAX_CHARGES) as _,
+            interaction: Interaction::None as _,
+            distribution_count: scene.distributions.len().min(FieldBaker::MAX_DISTRIBUTIONS) as _,
+            field_scale: 0.,
+            potential_scale: 0.,
+            display_scale: 0.,
+            charge_scale: 0.,
+            length_scale: 0.,
+            brick_count: field_baker.brick_count(),
+            near_cutoff: 0.,
+            near_count: 0,
+            domain_min: domain.min.into(),
+            _padding1: 0.,
+            domain_max: domain.max.into(),
+            _padding2: 0.,
+        };
+        field_params.set_units(&scene.units);
+        let field_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Field Params"),
+            contents: bytemuck::bytes_of(&field_params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
         let field_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("Field Sampler"),
-            address_mode_u: wgpu::AddressMode::MirrorRepeat,
-            address_mode_v: wgpu::AddressMode::MirrorRepeat,
-            address_mode_w: wgpu::AddressMode::MirrorRepeat,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Linear,
@@ -506,6 +916,86 @@ impl Context {
                         count: None,
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
         let field_texture_binding = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -520,59 +1010,255 @@ impl Context {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(&field_sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(
+                        &magnetic_texture.create_view(&Default::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: field_baker.charge_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: field_params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: poisson.electrode_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: barnes_hut.interaction_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: field_baker.distribution_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(field_baker.brick_atlas()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: field_baker.brick_buffer().as_entire_binding(),
+                },
             ],
         });
 
-        let time_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        let mut rng = StdRng::seed_from_u64(scene.seed);
+        let shared_uniform = SharedUniform {
+            seed: rng.gen(),
+            ..Default::default()
+        };
+        let time_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Time"),
-            size: std::mem::size_of::<[f32; 2]>() as _,
-            usage: wgpu::BufferUsages::UNIFORM,
-            mapped_at_creation: false,
+            contents: bytemuck::bytes_of(&shared_uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        let species_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Species"),
+            contents: bytemuck::bytes_of(&ParticleSpecies::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let mut emitters = Emitters::new(&device);
+        emitters.set_emitters(&queue, &scene.emitters);
+        let particle_boundaries = [ParticleBoundary::Respawn; 6];
+        let particle_boundary_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Particle Boundaries"),
+                contents: bytemuck::bytes_of(&ParticleBoundaryUniform::from(particle_boundaries)),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
         let time_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Time Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT
+                            | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
             });
-        let integrate_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&particle_bind_group_layout],
+        let time_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Time Bind Group"),
+            layout: &time_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: time_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: species_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: particle_boundary_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: emitters.emitter_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: emitters.params_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: emitters.counter_buffer().as_entire_binding(),
+                },
+            ],
+        });
+        let fill_shader = {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Fill Pipeline Layout"),
+                bind_group_layouts: &[&particle_bind_group_layout, &time_bind_group_layout],
                 push_constant_ranges: &[],
             });
             device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("Integration Pipeline"),
-                layout: Some(&layout),
+                label: Some("Fill Pipeline"),
+                layout: Some(&pipeline_layout),
                 module: &sim_shader,
-                entry_point: "integrate",
+                entry_point: "fill",
             })
         };
+        {
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            cpass.set_pipeline(&fill_shader);
+            cpass.set_bind_group(0, &particle_bind_group, &[]);
+            cpass.set_bind_group(1, &time_bind_group, &[]);
+            cpass.dispatch(dispatch_size(particle_num), 1, 1);
+            drop(cpass);
+            queue.submit(Some(encoder.finish()));
+        }
 
-        let simulation_pipeline = {
-            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        let simulation_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Simulation Pipeline Layout"),
                 bind_group_layouts: &[
                     &particle_bind_group_layout,
+                    &time_bind_group_layout,
                     &field_texture_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             });
+        let field_probe = FieldProbe::new(
+            &device,
+            &sim_shader,
+            &[
+                &particle_bind_group_layout,
+                &time_bind_group_layout,
+                &field_texture_bind_group_layout,
+            ],
+        );
+        let integrate_pipelines = Integrator::ALL.map(|integrator| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Integration Pipeline"),
+                layout: Some(&simulation_pipeline_layout),
+                module: &sim_shader,
+                entry_point: integrator.entry_point(),
+            })
+        });
+        let simulation_pipeline =
             device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
                 label: Some("Simulation Pipeline"),
-                layout: Some(&pipeline_layout),
+                layout: Some(&simulation_pipeline_layout),
                 module: &sim_shader,
                 entry_point: "compute_field",
-            })
-        };
+            });
+        let emit_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Emit Pipeline"),
+            layout: Some(&simulation_pipeline_layout),
+            module: &sim_shader,
+            entry_point: "emit_parked",
+        });
+        let reseed_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Reseed Pipeline"),
+            layout: Some(&simulation_pipeline_layout),
+            module: &sim_shader,
+            entry_point: "reseed",
+        });
+        let respawn_parked_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Respawn Parked Pipeline"),
+                layout: Some(&simulation_pipeline_layout),
+                module: &sim_shader,
+                entry_point: "respawn_parked",
+            });
+        let respawn_absorbed_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Respawn Absorbed Pipeline"),
+                layout: Some(&simulation_pipeline_layout),
+                module: &sim_shader,
+                entry_point: "respawn_absorbed",
+            });
+        let respawn_electrode_hits_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Respawn Electrode Hits Pipeline"),
+                layout: Some(&simulation_pipeline_layout),
+                module: &sim_shader,
+                entry_point: "respawn_electrode_hits",
+            });
 
         Self {
             surface,
@@ -589,17 +1275,59 @@ impl Context {
             camera_uniform,
 
             draw_particles_command,
+            particle_coloring: ParticleColoring::Position,
+            coloring,
+            coloring_buffer,
+            draw_isosurface_command,
             _particle_buffer: particle_buffer,
             particle_bind_group,
             particle_num,
 
-            rand_uniform,
-            _rand_uniform_binding: rand_uniform_binding,
-
-            integrate_pipeline,
+            rng,
+
+            shared_uniform,
+            time_buffer,
+            species: ParticleSpecies::default(),
+            species_buffer,
+            particle_boundaries,
+            particle_boundary_buffer,
+            emitters,
+            emit_pipeline,
+            reseed_pipeline,
+            respawn_parked_pipeline,
+            respawn_absorbed_pipeline,
+            respawn_electrode_hits_pipeline,
+            time_bind_group,
+
+            integrator: Integrator::Euler,
+            integrate_pipelines,
             simulation_pipeline,
 
+            field_baker,
+            units: scene.units,
+            charges: scene.charges.clone(),
+            distributions: scene.distributions.clone(),
+            charges_changed: false,
+            field_solver: FieldSolver::Coulomb,
+            field_mode: FieldMode::Texture,
+            interaction: Interaction::None,
+            poisson,
+            domain,
+            poisson_sweeps_left: 0,
+            space_charge,
+            barnes_hut,
+            isosurface,
+            equipotentials: Vec::new(),
+            isosurface_outdated: false,
+            macro_weight: Self::MACRO_WEIGHT,
+            field_params,
+            field_params_buffer,
+            magnetic_texture,
             field_texture_binding,
+
+            record_diagnostics: false,
+            particle_reduction,
+            field_probe,
         }
     }
 
@@ -630,31 +1358,479 @@ impl Context {
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
+    }
+
+    pub fn species(&self) -> ParticleSpecies {
+        self.species
+    }
+
+    /// Charge and mass given to particles from their next (re)spawn on.
+    /// Panics unless the mass is positive, the massive integrators divide by
+    /// it.
+    pub fn set_species(&mut self, species: ParticleSpecies) {
+        assert!(
+            species.mass > 0.,
+            "particle mass must be positive, got {}",
+            species.mass
+        );
+        self.species = species;
+        self.queue
+            .write_buffer(&self.species_buffer, 0, bytemuck::bytes_of(&species));
+    }
+
+    /// Particles are respawned by `emitters` from the next step on, uniformly
+    /// in the domain when there are none.
+    pub fn set_emitters(&mut self, emitters: &[Emitter]) {
+        self.emitters.set_emitters(&self.queue, emitters);
+        // Particles parked for the emitter budget have nothing to wait for.
+        if emitters.is_empty() {
+            self.dispatch_particles(&self.respawn_parked_pipeline);
+        }
+    }
+
+    pub fn seeding(&self) -> Seeding {
+        self.emitters.seeding()
+    }
+
+    /// Switches how particles are respawned and reseeds all of them right
+    /// away.
+    pub fn set_seeding(&mut self, seeding: Seeding) {
+        self.emitters.set_seeding(&self.queue, seeding);
+        self.dispatch_particles(&self.reseed_pipeline);
+    }
+
+    /// Runs one of the particle kernels outside of a simulation step.
+    fn dispatch_particles(&self, pipeline: &wgpu::ComputePipeline) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Reseed Encoder"),
+            });
+        let mut cpass = encoder.begin_compute_pass(&Default::default());
+        cpass.set_pipeline(pipeline);
+        cpass.set_bind_group(0, &self.particle_bind_group, &[]);
+        cpass.set_bind_group(1, &self.time_bind_group, &[]);
+        cpass.set_bind_group(2, &self.field_texture_binding, &[]);
+        cpass.dispatch(dispatch_size(self.particle_num), 1, 1);
+        drop(cpass);
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    pub fn equipotentials(&self) -> &[f32] {
+        &self.equipotentials
+    }
+
+    /// Potentials of the equipotential surfaces drawn over the particles, in
+    /// volts. At most [`Isosurface::MAX_LEVELS`] are used.
+    pub fn set_equipotentials(&mut self, levels: &[f32]) {
+        self.equipotentials = levels.to_vec();
+        let scale = self.units.potential_scale();
+        let levels: Vec<f32> = levels.iter().map(|level| level / scale).collect();
+        self.isosurface.set_levels(&self.queue, &levels);
+        self.isosurface_outdated = true;
+    }
+
+    pub fn particle_coloring(&self) -> ParticleColoring {
+        self.particle_coloring
+    }
+
+    pub fn set_particle_coloring(&mut self, coloring: ParticleColoring) {
+        self.particle_coloring = coloring;
+        self.coloring.mode = coloring as _;
+        self.queue
+            .write_buffer(&self.coloring_buffer, 0, bytemuck::bytes_of(&self.coloring));
+    }
+
+    pub fn particle_boundaries(&self) -> ParticleBoundaries {
+        self.particle_boundaries
+    }
+
+    /// Particles absorbed at the faces so far are brought back once no face
+    /// absorbs any more, with emitters within their budget.
+    pub fn set_particle_boundaries(&mut self, boundaries: ParticleBoundaries) {
+        let absorbing =
+            |boundaries: &ParticleBoundaries| boundaries.contains(&ParticleBoundary::Absorb);
+        let was_absorbing = absorbing(&self.particle_boundaries);
+        self.particle_boundaries = boundaries;
+        self.queue.write_buffer(
+            &self.particle_boundary_buffer,
+            0,
+            bytemuck::bytes_of(&ParticleBoundaryUniform::from(boundaries)),
+        );
+        if was_absorbing && !absorbing(&boundaries) {
+            self.dispatch_particles(&self.respawn_absorbed_pipeline);
+        }
+    }
+
+    /// Replaces every field source with the ones of `scene`.
+    pub fn set_scene(&mut self, scene: &Scene) {
+        self.set_units(&scene.units);
+        self.set_charges(&scene.charges);
+        self.set_distributions(&scene.distributions);
+        self.set_magnets(&scene.magnets);
+        self.set_electrodes(&scene.electrodes);
+        self.set_dielectrics(&scene.dielectrics);
+        self.set_boundaries(&scene.boundaries);
+        self.set_emitters(&scene.emitters);
+    }
+
+    /// Replaces the charges the field texture is baked from. The texture is
+    /// re-baked on the GPU before the next simulation step.
+    pub fn set_charges(&mut self, charges: &[Charge]) {
+        self.field_baker.set_charges(&self.queue, charges);
+        self.charges_changed = true;
+        self.field_params.charge_count = charges.len().min(FieldBaker::MAX_CHARGES) as _;
+        self.update_texture_sampling();
+        self.charges = charges.to_vec();
+        self.set_charge_density(&charge_density(
+            &self.charges,
+            &self.distributions,
+            &self.domain,
+        ));
+    }
+
+    /// Replaces the extended and multipole sources, baked and evaluated
+    /// analytically next to the point charges.
+    pub fn set_distributions(&mut self, distributions: &[ChargeDistribution]) {
+        self.field_baker
+            .set_distributions(&self.queue, distributions);
+        self.charges_changed = true;
+        self.field_params.distribution_count =
+            distributions.len().min(FieldBaker::MAX_DISTRIBUTIONS) as _;
+        self.write_field_params();
+        self.distributions = distributions.to_vec();
+        self.set_charge_density(&charge_density(
+            &self.charges,
+            &self.distributions,
+            &self.domain,
+        ));
+    }
+
+    /// Re-bakes the magnetic field texture on the CPU.
+    pub fn set_magnets(&mut self, magnets: &[MagneticSource]) {
+        write_field_texture(&self.queue, &self.magnetic_texture, &self.domain, |p| {
+            get_magnetic_field(p, magnets)
+        });
+    }
+
+    /// Replaces the charge density volume of the Poisson solver, one value
+    /// per field texture voxel in x-major order.
+    pub fn set_charge_density(&mut self, density: &[f32]) {
+        self.poisson.set_density(&self.queue, density);
+        self.poisson_sweeps_left = Self::POISSON_SWEEPS;
+    }
+
+    pub fn units(&self) -> Units {
+        self.units
+    }
+
+    /// Rescales the field, the particle dynamics, the rendering and the
+    /// equipotential levels. Electrode and boundary potentials are converted
+    /// when they are set, so they are best set again afterwards.
+    pub fn set_units(&mut self, units: &Units) {
+        self.units = *units;
+        self.field_params.set_units(units);
+        self.write_field_params();
+        self.coloring.potential_scale = Self::POTENTIAL_COLOR_SCALE / units.potential_scale();
         self.queue
-            .write_buffer(&self.rand_uniform, 0, &rand::random::<f32>().to_le_bytes());
+            .write_buffer(&self.coloring_buffer, 0, bytemuck::bytes_of(&self.coloring));
+        let equipotentials = std::mem::take(&mut self.equipotentials);
+        self.set_equipotentials(&equipotentials);
+    }
+
+    /// Rasterizes the electrodes into the Poisson grid. They act as fixed
+    /// potentials in the solve and absorb particles in every field mode, the
+    /// particles absorbed by the previous ones are respawned.
+    pub fn set_electrodes(&mut self, electrodes: &[Electrode]) {
+        self.poisson
+            .set_electrodes(&self.queue, &scene_electrodes(electrodes, &self.units));
+        self.poisson_sweeps_left = Self::POISSON_SWEEPS;
+        self.dispatch_particles(&self.respawn_electrode_hits_pipeline);
+    }
+
+    /// Rasterizes the permittivity of the dielectrics into the Poisson grid,
+    /// the Coulomb solver keeps treating the whole domain as vacuum.
+    pub fn set_dielectrics(&mut self, dielectrics: &[Dielectric]) {
+        self.poisson.set_dielectrics(&self.queue, dielectrics);
+        self.poisson_sweeps_left = Self::POISSON_SWEEPS;
+    }
+
+    pub fn set_boundaries(&mut self, boundaries: &Boundaries) {
+        self.poisson
+            .set_boundaries(&self.queue, &scene_boundaries(boundaries, &self.units));
+        self.poisson_sweeps_left = Self::POISSON_SWEEPS;
+    }
+
+    pub fn field_solver(&self) -> FieldSolver {
+        self.field_solver
+    }
+
+    pub fn set_field_solver(&mut self, solver: FieldSolver) {
+        self.field_solver = solver;
+        match solver {
+            FieldSolver::Coulomb => self.charges_changed = true,
+            FieldSolver::Poisson => {
+                self.poisson.set_space_charge_scale(&self.queue, 0.);
+                self.poisson_sweeps_left = Self::POISSON_SWEEPS;
+            }
+            FieldSolver::Pic => {}
+        }
+        self.update_texture_sampling();
+    }
+
+    /// The refinement bricks and the near field split only apply to the
+    /// charges, so the grid solvers sample the field texture alone.
+    fn update_texture_sampling(&mut self) {
+        let coulomb = self.field_solver == FieldSolver::Coulomb;
+        self.field_params.brick_count = if coulomb {
+            self.field_baker.brick_count()
+        } else {
+            0
+        };
+        let near_cutoff = if coulomb && self.field_mode() == FieldMode::Hybrid {
+            self.domain.spacing().max_element() * Self::NEAR_CUTOFF_CELLS
+        } else {
+            0.
+        };
+        if near_cutoff != self.field_params.near_cutoff {
+            self.field_baker.set_near_cutoff(&self.queue, near_cutoff);
+            self.charges_changed = true;
+        }
+        self.field_params.near_cutoff = near_cutoff;
+        self.field_params.near_count = if coulomb {
+            self.field_baker.near_count()
+        } else {
+            0
+        };
+        self.write_field_params();
+    }
+
+    pub fn interaction(&self) -> Interaction {
+        self.interaction
+    }
+
+    pub fn set_interaction(&mut self, interaction: Interaction) {
+        self.interaction = interaction;
+        self.field_params.interaction = interaction as _;
+        self.write_field_params();
+    }
+
+    pub fn field_mode(&self) -> FieldMode {
+        self.field_mode
+    }
+
+    pub fn set_field_mode(&mut self, mode: FieldMode) {
+        self.field_mode = mode;
+        self.field_params.mode = mode as _;
+        self.update_texture_sampling();
+    }
+
+    fn write_field_params(&self) {
+        self.queue.write_buffer(
+            &self.field_params_buffer,
+            0,
+            bytemuck::bytes_of(&self.field_params),
+        );
     }
 
-    pub fn simulate(&mut self, _dt: f32) {
+    /// Records the bake or up to `max_sweeps` of the Poisson sweeps still
+    /// pending after the sources changed, returns whether the field texture
+    /// is written. [`FieldSolver::Pic`] is left to [`Context::simulate`], its
+    /// field follows the particles rather than the sources.
+    fn update_static_field(&mut self, encoder: &mut wgpu::CommandEncoder, max_sweeps: u32) -> bool {
+        match self.field_solver {
+            FieldSolver::Coulomb if self.charges_changed => {
+                self.field_baker.bake(encoder);
+                self.charges_changed = false;
+                true
+            }
+            FieldSolver::Poisson if self.poisson_sweeps_left > 0 => {
+                let sweeps = self.poisson_sweeps_left.min(max_sweeps);
+                self.poisson.solve(encoder, sweeps);
+                self.poisson_sweeps_left -= sweeps;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs the bake or the Poisson sweeps still pending, which
+    /// [`Context::simulate`] otherwise spreads over the next steps, so the
+    /// field texture matches the current sources. The PIC field is not
+    /// flushed, it is only ever as current as the last step.
+    fn flush_field(&mut self) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Flush Field Encoder"),
+            });
+        if self.update_static_field(&mut encoder, u32::MAX) {
+            self.isosurface_outdated = true;
+            self.queue.submit(Some(encoder.finish()));
+        }
+    }
+
+    pub fn simulate(&mut self, dt: f32) {
+        self.shared_uniform.dt = dt;
+        self.shared_uniform.time += dt;
+        self.shared_uniform.seed = self.rng.gen();
+        self.queue.write_buffer(
+            &self.time_buffer,
+            0,
+            bytemuck::bytes_of(&self.shared_uniform),
+        );
+
+        self.emitters.update(&self.queue, dt, self.particle_num);
+
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Compute Encoder"),
             });
 
+        let field_updated = match self.field_solver {
+            FieldSolver::Pic => {
+                self.poisson.set_space_charge_scale(
+                    &self.queue,
+                    self.macro_weight / SpaceCharge::FIXED_POINT_SCALE,
+                );
+                self.space_charge.deposit(&mut encoder);
+                self.poisson
+                    .solve(&mut encoder, Self::POISSON_SWEEPS_PER_STEP);
+                true
+            }
+            _ => self.update_static_field(&mut encoder, Self::POISSON_SWEEPS_PER_STEP),
+        };
+
+        // Extracted by `render`, once however many steps a frame takes.
+        self.isosurface_outdated |= field_updated;
+
+        if self.interaction() == Interaction::BarnesHut {
+            self.barnes_hut
+                .set_charge_scale(&self.queue, self.macro_weight);
+            self.barnes_hut.compute(&mut encoder);
+        }
+
         let mut cpass = encoder.begin_compute_pass(&Default::default());
 
-        cpass.set_pipeline(&self.simulation_pipeline);
         cpass.set_bind_group(0, &self.particle_bind_group, &[]);
-        cpass.set_bind_group(1, &self.field_texture_binding, &[]);
-        cpass.dispatch(dispatch_size(self.particle_num), 1, 1);
+        cpass.set_bind_group(1, &self.time_bind_group, &[]);
+        cpass.set_bind_group(2, &self.field_texture_binding, &[]);
 
-        cpass.set_pipeline(&self.integrate_pipeline);
-        cpass.set_bind_group(0, &self.particle_bind_group, &[]);
+        if self.integrator == Integrator::Euler {
+            cpass.set_pipeline(&self.simulation_pipeline);
+            cpass.dispatch(dispatch_size(self.particle_num), 1, 1);
+        }
+
+        cpass.set_pipeline(&self.integrate_pipelines[self.integrator as usize]);
         cpass.dispatch(dispatch_size(self.particle_num), 1, 1);
 
+        if !self.emitters.is_empty() {
+            cpass.set_pipeline(&self.emit_pipeline);
+            cpass.dispatch(dispatch_size(self.particle_num), 1, 1);
+        }
+
         drop(cpass);
 
+        if self.record_diagnostics {
+            self.particle_reduction.reduce(
+                &self.device,
+                &mut encoder,
+                self.shared_uniform.time,
+                &self.units,
+            );
+        }
+
         self.queue.submit(Some(encoder.finish()));
+        self.particle_reduction.map_recorded();
+    }
+
+    /// Field and potential at the probe points as the particles see them,
+    /// sampled on the GPU. Blocks until the results are read back.
+    pub fn probe(&mut self, probe: &Probe) -> Vec<ProbeSample> {
+        self.flush_field();
+        let results = self.field_probe.sample(
+            &self.device,
+            &self.queue,
+            &[
+                &self.particle_bind_group,
+                &self.time_bind_group,
+                &self.field_texture_binding,
+            ],
+            &probe.points,
+        );
+        probe
+            .points
+            .iter()
+            .zip(&probe.distances)
+            .zip(results)
+            .map(|((&pos, &distance), result)| ProbeSample {
+                distance,
+                pos,
+                field: result.truncate(),
+                potential: result.w,
+            })
+            .collect()
+    }
+
+    /// CPU reference of [`Context::probe`], the Coulomb field of the charges
+    /// and distributions alone.
+    pub fn probe_reference(&self, probe: &Probe) -> Vec<ProbeSample> {
+        probe
+            .points
+            .iter()
+            .zip(&probe.distances)
+            .map(|(&pos, &distance)| ProbeSample {
+                distance,
+                pos,
+                field: get_field(pos, &self.charges, &self.distributions)
+                    * self.units.field_scale(),
+                potential: get_potential(pos, &self.charges, &self.distributions)
+                    * self.units.potential_scale(),
+            })
+            .collect()
+    }
+
+    /// Flux of the electric field through `surface` from both
+    /// [`Context::probe_reference`] and [`Context::probe`], with `resolution`
+    /// quadrature cells along each direction of the surface. The enclosed
+    /// charge counts the distributions through their point charges.
+    pub fn gauss_flux(&mut self, surface: &GaussSurface, resolution: u32) -> GaussFlux {
+        let (probe, areas) = surface.quadrature(resolution);
+        let area_scale = self.units.length_scale * self.units.length_scale;
+        let flux = |samples: Vec<ProbeSample>| {
+            let flux: f32 = samples
+                .iter()
+                .zip(&areas)
+                .map(|(sample, area)| sample.field.dot(*area))
+                .sum();
+            flux * area_scale
+        };
+        let enclosed: f32 = self
+            .charges
+            .iter()
+            .copied()
+            .chain(
+                self.distributions
+                    .iter()
+                    .flat_map(|distribution| distribution.point_charges(&self.domain)),
+            )
+            .filter(|charge| surface.contains(charge.pos))
+            .map(|charge| charge.q)
+            .sum();
+        GaussFlux {
+            enclosed_charge: enclosed * self.units.charge_unit.coulombs(),
+            expected: 4. * std::f32::consts::PI * enclosed * self.units.field_scale() * area_scale,
+            analytic: flux(self.probe_reference(&probe)),
+            sampled: flux(self.probe(&probe)),
+        }
+    }
+
+    /// Diagnostics of the steps whose readback completed since the last call,
+    /// oldest first.
+    pub fn poll_diagnostics(&mut self) -> Vec<Diagnostics> {
+        self.device.poll(wgpu::Maintain::Poll);
+        self.particle_reduction.collect()
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -669,6 +1845,14 @@ impl Context {
                 label: Some("Render Encoder"),
             });
 
+        // The hybrid texture lacks the near field, so it has no equipotentials
+        // worth drawing.
+        let isosurface_shown = self.field_params.near_cutoff == 0.;
+        if isosurface_shown && !self.isosurface.is_empty() && self.isosurface_outdated {
+            self.isosurface.extract(&self.queue, &mut encoder);
+            self.isosurface_outdated = false;
+        }
+
         {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -695,10 +1879,11 @@ impl Context {
                 }),
             });
             rpass.execute_bundles(
-                [&self.draw_lines_command, &self.draw_particles_command]
-                    .iter()
-                    .cloned(),
+                [&self.draw_lines_command, &self.draw_particles_command].into_iter(),
             );
+            if isosurface_shown {
+                rpass.execute_bundles(std::iter::once(&self.draw_isosurface_command));
+            }
         }
         self.queue.submit(Some(encoder.finish()));
         frame.present();
@@ -0,0 +1,436 @@
+use std::cmp::Ordering;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gfx_ctx::{create_field_texture, workgroup_count},
+    scene::{Charge, ChargeDistribution, Domain},
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuCharge {
+    pos: [f32; 3],
+    q: f32,
+}
+
+impl From<Charge> for GpuCharge {
+    fn from(charge: Charge) -> Self {
+        Self {
+            pos: charge.pos.into(),
+            q: charge.q,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuDistribution {
+    origin: [f32; 3],
+    kind: u32,
+    axis: [f32; 3],
+    strength: f32,
+    radius: f32,
+    _padding: [f32; 3],
+}
+
+impl From<ChargeDistribution> for GpuDistribution {
+    fn from(distribution: ChargeDistribution) -> Self {
+        let (kind, origin, axis, strength, radius) = match distribution {
+            ChargeDistribution::Line { start, end, charge } => (0, start, end - start, charge, 0.),
+            ChargeDistribution::Ring {
+                center,
+                normal,
+                radius,
+                charge,
+            } => (1, center, normal.normalize(), charge, radius),
+            ChargeDistribution::Disc {
+                center,
+                normal,
+                radius,
+                charge,
+            } => (2, center, normal.normalize(), charge, radius),
+            ChargeDistribution::Plane {
+                point,
+                normal,
+                density,
+            } => (3, point, normal.normalize(), density, 0.),
+            ChargeDistribution::Dipole { pos, moment } => (4, pos, moment, 0., 0.),
+            ChargeDistribution::Quadrupole { pos, axis, moment } => {
+                (5, pos, axis.normalize(), moment, 0.)
+            }
+        };
+        Self {
+            origin: origin.into(),
+            kind,
+            axis: axis.into(),
+            strength,
+            radius,
+            _padding: [0.; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct BakeParams {
+    size: [u32; 3],
+    charge_count: u32,
+    origin: [f32; 3],
+    distribution_count: u32,
+    spacing: [f32; 3],
+    brick_count: u32,
+    near_cutoff: f32,
+    near_count: u32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuBrick {
+    min: [f32; 3],
+    spacing: f32,
+}
+
+/// Evaluates the charge list for every voxel of the field texture on the GPU,
+/// E in `xyz` and the potential in `w`.
+///
+/// The field diverges at the point charges, so each of them also gets
+/// [`FieldBaker::REFINEMENT_LEVELS`] nested bricks of
+/// [`FieldBaker::BRICK_SIZE`]³ nodes centered on it, every level twice as
+/// fine as the previous one. The bricks are baked side by side into an atlas
+/// texture and sampled in place of the field texture wherever they cover.
+pub struct FieldBaker {
+    params: BakeParams,
+    params_buffer: wgpu::Buffer,
+    charge_buffer: wgpu::Buffer,
+    distribution_buffer: wgpu::Buffer,
+    brick_buffer: wgpu::Buffer,
+    brick_atlas: wgpu::TextureView,
+    /// Width of the coarsest brick.
+    brick_width: f32,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    brick_pipeline: wgpu::ComputePipeline,
+}
+
+impl FieldBaker {
+    pub const MAX_CHARGES: usize = 1024;
+    pub const MAX_DISTRIBUTIONS: usize = 256;
+    /// Nodes along each axis of a brick, `BRICK_SIZE` in `charge.wgsl`.
+    pub const BRICK_SIZE: u32 = 16;
+    pub const MAX_BRICKS: usize = 64;
+    pub const REFINEMENT_LEVELS: usize = 3;
+    const WORKGROUP_SIZE: u32 = 4;
+
+    pub fn new(device: &wgpu::Device, field_texture: &wgpu::TextureView, domain: &Domain) -> Self {
+        let params = BakeParams {
+            size: domain.resolution,
+            charge_count: 0,
+            origin: domain.min.into(),
+            distribution_count: 0,
+            spacing: domain.spacing().into(),
+            brick_count: 0,
+            near_cutoff: 0.,
+            near_count: 0,
+            _padding: [0; 2],
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Bake Params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let charge_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Charges"),
+            size: (Self::MAX_CHARGES * std::mem::size_of::<GpuCharge>()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let distribution_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Charge Distributions"),
+            size: (Self::MAX_DISTRIBUTIONS * std::mem::size_of::<GpuDistribution>()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let brick_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Field Bricks"),
+            size: (Self::MAX_BRICKS * std::mem::size_of::<GpuBrick>()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let brick_atlas = create_field_texture(
+            device,
+            "Field Brick Atlas",
+            [
+                Self::BRICK_SIZE * Self::MAX_BRICKS as u32,
+                Self::BRICK_SIZE,
+                Self::BRICK_SIZE,
+            ],
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        )
+        .create_view(&Default::default());
+        let brick_width = domain.size().max_element() / 8.;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bake Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bake Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: charge_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(field_texture),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: distribution_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: brick_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&brick_atlas),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("bake.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("../charge.wgsl"), include_str!("bake.wgsl")).into(),
+            ),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bake Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Bake Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "bake_field",
+        });
+        let brick_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Bake Bricks Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "bake_bricks",
+        });
+
+        Self {
+            params,
+            params_buffer,
+            charge_buffer,
+            distribution_buffer,
+            brick_buffer,
+            brick_atlas,
+            brick_width,
+            bind_group,
+            pipeline,
+            brick_pipeline,
+        }
+    }
+
+    pub fn charge_buffer(&self) -> &wgpu::Buffer {
+        &self.charge_buffer
+    }
+
+    pub fn distribution_buffer(&self) -> &wgpu::Buffer {
+        &self.distribution_buffer
+    }
+
+    pub fn brick_buffer(&self) -> &wgpu::Buffer {
+        &self.brick_buffer
+    }
+
+    pub fn brick_atlas(&self) -> &wgpu::TextureView {
+        &self.brick_atlas
+    }
+
+    pub fn brick_count(&self) -> u32 {
+        self.params.brick_count
+    }
+
+    /// Leading charges the hybrid field mode evaluates analytically, the
+    /// strongest ones, which also get the refinement bricks.
+    pub fn near_count(&self) -> u32 {
+        self.params.near_count
+    }
+
+    /// Uploads the charges strongest first, so the bricks and the near field
+    /// only need to look at the start of the buffer. Beyond
+    /// [`FieldBaker::MAX_CHARGES`] the weakest ones are left out.
+    pub fn set_charges(&mut self, queue: &wgpu::Queue, charges: &[Charge]) {
+        let mut charges: Vec<GpuCharge> = charges.iter().map(|&charge| charge.into()).collect();
+        charges.sort_by(|a, b| b.q.abs().partial_cmp(&a.q.abs()).unwrap_or(Ordering::Equal));
+        if charges.len() > Self::MAX_CHARGES {
+            log::warn!(
+                "Only the {} strongest of {} charges are baked",
+                Self::MAX_CHARGES,
+                charges.len()
+            );
+            charges.truncate(Self::MAX_CHARGES);
+        }
+        if !charges.is_empty() {
+            queue.write_buffer(&self.charge_buffer, 0, bytemuck::cast_slice(&charges));
+        }
+        self.params.charge_count = charges.len() as _;
+        self.params.near_count = charges
+            .len()
+            .min(Self::MAX_BRICKS / Self::REFINEMENT_LEVELS) as _;
+        self.set_bricks(queue, &charges);
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    /// Leaves the field within `cutoff` of the charges out of the bake, see
+    /// `near_weight` in `charge.wgsl`. Zero bakes the whole field.
+    pub fn set_near_cutoff(&mut self, queue: &wgpu::Queue, cutoff: f32) {
+        self.params.near_cutoff = cutoff;
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    /// Places the refinement bricks around the near charges, `charges` is
+    /// sorted strongest first.
+    fn set_bricks(&mut self, queue: &wgpu::Queue, charges: &[GpuCharge]) {
+        let last = (Self::BRICK_SIZE - 1) as f32;
+        let brick_width = self.brick_width;
+        let bricks: Vec<GpuBrick> = charges
+            .iter()
+            .take(self.params.near_count as _)
+            .flat_map(|charge| {
+                (0..Self::REFINEMENT_LEVELS).map(move |level| {
+                    let width = brick_width / (1 << level) as f32;
+                    GpuBrick {
+                        min: charge.pos.map(|x| x - width / 2.),
+                        spacing: width / last,
+                    }
+                })
+            })
+            .collect();
+        if !bricks.is_empty() {
+            queue.write_buffer(&self.brick_buffer, 0, bytemuck::cast_slice(&bricks));
+        }
+        self.params.brick_count = bricks.len() as _;
+    }
+
+    /// Beyond [`FieldBaker::MAX_DISTRIBUTIONS`] the last ones are left out.
+    pub fn set_distributions(&mut self, queue: &wgpu::Queue, distributions: &[ChargeDistribution]) {
+        if distributions.len() > Self::MAX_DISTRIBUTIONS {
+            log::warn!(
+                "Only the first {} of {} charge distributions are baked",
+                Self::MAX_DISTRIBUTIONS,
+                distributions.len()
+            );
+        }
+        let distributions: Vec<GpuDistribution> = distributions
+            .iter()
+            .take(Self::MAX_DISTRIBUTIONS)
+            .map(|&distribution| distribution.into())
+            .collect();
+        if !distributions.is_empty() {
+            queue.write_buffer(
+                &self.distribution_buffer,
+                0,
+                bytemuck::cast_slice(&distributions),
+            );
+        }
+        self.params.distribution_count = distributions.len() as _;
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    pub fn bake(&self, encoder: &mut wgpu::CommandEncoder) {
+        let [x, y, z] = self
+            .params
+            .size
+            .map(|len| workgroup_count(len, Self::WORKGROUP_SIZE));
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Bake Field"),
+        });
+        cpass.set_pipeline(&self.pipeline);
+        cpass.set_bind_group(0, &self.bind_group, &[]);
+        cpass.dispatch(x, y, z);
+
+        if self.params.brick_count > 0 {
+            let width = Self::BRICK_SIZE * self.params.brick_count;
+            let [x, y, z] = [width, Self::BRICK_SIZE, Self::BRICK_SIZE]
+                .map(|len| workgroup_count(len, Self::WORKGROUP_SIZE));
+            cpass.set_pipeline(&self.brick_pipeline);
+            cpass.dispatch(x, y, z);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+[[block]]
+struct BakeParams {
+  size: vec3<u32>;
+  charge_count: u32;
+  // World position of the first node and the distance between nodes.
+  origin: vec3<f32>;
+  distribution_count: u32;
+  spacing: vec3<f32>;
+  brick_count: u32;
+  // Radius around the charges left to the hybrid field mode, zero to bake
+  // the whole field.
+  near_cutoff: f32;
+  // Leading charges the cutoff applies to, the others are always baked.
+  near_count: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: BakeParams;
+[[group(0), binding(1)]]
+var<storage, read> charges: Charges;
+[[group(0), binding(2)]]
+var field_texture: texture_storage_3d<rgba32float, write>;
+[[group(0), binding(3)]]
+var<storage, read> distributions: Distributions;
+[[group(0), binding(4)]]
+var<storage, read> bricks: Bricks;
+// The bricks side by side along x.
+[[group(0), binding(5)]]
+var brick_atlas: texture_storage_3d<rgba32float, write>;
+
+fn evaluate_sources(p: vec3<f32>) -> vec4<f32> {
+  var field = vec3<f32>(0.);
+  var potential = 0.;
+  for (var i = 0u; i < params.charge_count; i = i + 1u) {
+    let charge = charges.data[i];
+    var far = 1.;
+    if (params.near_cutoff > 0. && i < params.near_count) {
+      far = 1. - near_weight(p, charge, params.near_cutoff);
+    }
+    if (far > 0.) {
+      field = field + get_charge(p, charge) * far;
+      potential = potential + get_charge_potential(p, charge) * far;
+    }
+  }
+  for (var i = 0u; i < params.distribution_count; i = i + 1u) {
+    let res = get_distribution(p, distributions.data[i]);
+    field = field + res.xyz;
+    potential = potential + res.w;
+  }
+  return vec4<f32>(field, potential);
+}
+
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn bake_field(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  if (any(global_id >= params.size)) {
+    return;
+  }
+  let p = params.origin + vec3<f32>(global_id) * params.spacing;
+  textureStore(field_texture, vec3<i32>(global_id), evaluate_sources(p));
+}
+
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn bake_bricks(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let index = global_id.x / BRICK_SIZE;
+  if (index >= params.brick_count || any(global_id.yz >= vec2<u32>(BRICK_SIZE))) {
+    return;
+  }
+  let brick = bricks.data[index];
+  let node = vec3<u32>(global_id.x % BRICK_SIZE, global_id.yz);
+  let p = brick.min + vec3<f32>(node) * brick.spacing;
+  textureStore(brick_atlas, vec3<i32>(global_id), evaluate_sources(p));
+}
@@ -0,0 +1,414 @@
+use std::{
+    future::Future,
+    io::{self, Write},
+    pin::Pin,
+    task::{self, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use crate::{gfx_ctx::workgroup_count, scene::Units};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ReduceParams {
+    particle_count: u32,
+    partial_count: u32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuMoments {
+    bounds_min: [f32; 3],
+    alive: u32,
+    bounds_max: [f32; 3],
+    kinetic: f32,
+    momentum: [f32; 3],
+    potential: f32,
+    position_sum: [f32; 3],
+    _padding: f32,
+}
+
+/// Totals over the living particles at the end of a simulation step.
+///
+/// Energies are in joules and momentum in kg m/s through the scene [`Units`],
+/// with the particle mass taken in kilograms. They describe the motion of the
+/// massive integrators, the tracers are counted with their velocity along the
+/// field all the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Diagnostics {
+    pub step: u64,
+    pub time: f32,
+    pub alive: u32,
+    pub kinetic_energy: f32,
+    /// `q * φ` of the particles in the field, the particle-particle
+    /// interaction is left out.
+    pub potential_energy: f32,
+    pub momentum: Vec3,
+    /// Bounding box and centroid of the living particles in world units, all
+    /// zero without any.
+    pub bounds_min: Vec3,
+    pub bounds_max: Vec3,
+    pub centroid: Vec3,
+}
+
+impl Diagnostics {
+    pub const CSV_HEADER: &'static str = "step,time,alive,kinetic_energy,potential_energy,\
+        total_energy,momentum_x,momentum_y,momentum_z,min_x,min_y,min_z,max_x,max_y,max_z,\
+        centroid_x,centroid_y,centroid_z";
+
+    fn new(moments: &GpuMoments, step: u64, time: f32, units: &Units) -> Self {
+        let length_scale = units.length_scale;
+        let (bounds_min, bounds_max, centroid) = match moments.alive {
+            0 => (Vec3::ZERO, Vec3::ZERO, Vec3::ZERO),
+            alive => (
+                moments.bounds_min.into(),
+                moments.bounds_max.into(),
+                Vec3::from(moments.position_sum) / alive as f32,
+            ),
+        };
+        Self {
+            step,
+            time,
+            alive: moments.alive,
+            kinetic_energy: 0.5 * moments.kinetic * length_scale * length_scale,
+            potential_energy: moments.potential * units.charge_unit.coulombs(),
+            momentum: Vec3::from(moments.momentum) * length_scale,
+            bounds_min,
+            bounds_max,
+            centroid,
+        }
+    }
+
+    pub fn total_energy(&self) -> f32 {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    /// Writes one line in the column order of [`Diagnostics::CSV_HEADER`].
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        let Self {
+            momentum: p,
+            bounds_min: min,
+            bounds_max: max,
+            centroid: c,
+            ..
+        } = self;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.step,
+            self.time,
+            self.alive,
+            self.kinetic_energy,
+            self.potential_energy,
+            self.total_energy(),
+            p.x,
+            p.y,
+            p.z,
+            min.x,
+            min.y,
+            min.z,
+            max.x,
+            max.y,
+            max.z,
+            c.x,
+            c.y,
+            c.z,
+        )
+    }
+}
+
+/// Waker of the readback futures, they are polled every frame instead of
+/// being woken.
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    // SAFETY: the vtable functions ignore the data pointer.
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+enum Readback {
+    Free,
+    /// The reduction result is copied in by a submission not made yet.
+    Recorded {
+        step: u64,
+        time: f32,
+        units: Units,
+    },
+    Mapping {
+        step: u64,
+        time: f32,
+        units: Units,
+        mapped: MapFuture,
+    },
+}
+
+/// Parallel reduction of the particle buffer into [`Diagnostics`], read back
+/// without stalling the simulation.
+///
+/// Every workgroup of the first pass sums its particles into a partial, a
+/// single workgroup sums the partials. The result is copied into one of a few
+/// readback buffers that are mapped asynchronously and collected a frame or
+/// so later, another one is added when a step finds all of them in flight.
+/// No step is skipped as long as the readbacks are collected every frame.
+pub struct ParticleReduction {
+    partial_count: u32,
+    step: u64,
+    result_buffer: wgpu::Buffer,
+    readback_buffers: Vec<wgpu::Buffer>,
+    readbacks: Vec<Readback>,
+    bind_group: wgpu::BindGroup,
+    particle_pipeline: wgpu::ComputePipeline,
+    partial_pipeline: wgpu::ComputePipeline,
+}
+
+impl ParticleReduction {
+    /// `WORKGROUP_SIZE` in `diagnostics.wgsl`.
+    const WORKGROUP_SIZE: u32 = 128;
+    /// Readback buffers created up front.
+    const READBACK_BUFFERS: usize = 8;
+
+    pub fn new(device: &wgpu::Device, particle_buffer: &wgpu::Buffer, particle_count: u32) -> Self {
+        let partial_count = workgroup_count(particle_count, Self::WORKGROUP_SIZE);
+        let params = ReduceParams {
+            particle_count,
+            partial_count,
+            _padding: [0; 2],
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Reduce Params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let moments_size = std::mem::size_of::<GpuMoments>() as u64;
+        let partial_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reduce Partials"),
+            size: partial_count.max(1) as u64 * moments_size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let result_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reduce Result"),
+            size: moments_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffers = (0..Self::READBACK_BUFFERS)
+            .map(|_| Self::create_readback_buffer(device))
+            .collect();
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Reduce Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Reduce Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: partial_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: result_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("diagnostics.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("diagnostics.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Reduce Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let particle_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Reduce Particles Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "reduce_particles",
+        });
+        let partial_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Reduce Partials Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "reduce_partials",
+        });
+
+        Self {
+            partial_count,
+            step: 0,
+            result_buffer,
+            readback_buffers,
+            readbacks: (0..Self::READBACK_BUFFERS)
+                .map(|_| Readback::Free)
+                .collect(),
+            bind_group,
+            particle_pipeline,
+            partial_pipeline,
+        }
+    }
+
+    fn create_readback_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Diagnostics Readback"),
+            size: std::mem::size_of::<GpuMoments>() as _,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Counts a simulation step and reduces the particles as they are at the
+    /// end of it.
+    pub fn reduce(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        time: f32,
+        units: &Units,
+    ) {
+        self.step += 1;
+        let slot = match self
+            .readbacks
+            .iter()
+            .position(|readback| matches!(readback, Readback::Free))
+        {
+            Some(slot) => slot,
+            None => {
+                self.readback_buffers
+                    .push(Self::create_readback_buffer(device));
+                self.readbacks.push(Readback::Free);
+                self.readbacks.len() - 1
+            }
+        };
+
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Reduce Particles"),
+        });
+        cpass.set_bind_group(0, &self.bind_group, &[]);
+        cpass.set_pipeline(&self.particle_pipeline);
+        cpass.dispatch(self.partial_count, 1, 1);
+        cpass.set_pipeline(&self.partial_pipeline);
+        cpass.dispatch(1, 1, 1);
+        drop(cpass);
+
+        encoder.copy_buffer_to_buffer(
+            &self.result_buffer,
+            0,
+            &self.readback_buffers[slot],
+            0,
+            std::mem::size_of::<GpuMoments>() as _,
+        );
+        self.readbacks[slot] = Readback::Recorded {
+            step: self.step,
+            time,
+            units: *units,
+        };
+    }
+
+    /// Starts mapping the readback buffers recorded into, to be called once
+    /// the encoder passed to [`ParticleReduction::reduce`] is submitted.
+    pub fn map_recorded(&mut self) {
+        for (buffer, readback) in self.readback_buffers.iter().zip(&mut self.readbacks) {
+            if let Readback::Recorded { step, time, units } = *readback {
+                *readback = Readback::Mapping {
+                    step,
+                    time,
+                    units,
+                    mapped: Box::pin(buffer.slice(..).map_async(wgpu::MapMode::Read)),
+                };
+            }
+        }
+    }
+
+    /// Diagnostics of the readbacks that completed since the last call,
+    /// oldest first. The device has to be polled for them to progress.
+    pub fn collect(&mut self) -> Vec<Diagnostics> {
+        let waker = noop_waker();
+        let mut cx = task::Context::from_waker(&waker);
+        let mut diagnostics = vec![];
+        for (buffer, readback) in self.readback_buffers.iter().zip(&mut self.readbacks) {
+            let (step, time, units, mapped) = match readback {
+                Readback::Mapping {
+                    step,
+                    time,
+                    units,
+                    mapped,
+                } => (*step, *time, *units, mapped),
+                _ => continue,
+            };
+            match mapped.as_mut().poll(&mut cx) {
+                Poll::Pending => continue,
+                Poll::Ready(Ok(())) => {
+                    let moments = *bytemuck::from_bytes(&buffer.slice(..).get_mapped_range());
+                    diagnostics.push(Diagnostics::new(&moments, step, time, &units));
+                    buffer.unmap();
+                }
+                Poll::Ready(Err(err)) => eprintln!("Diagnostics readback failed: {:?}", err),
+            }
+            *readback = Readback::Free;
+        }
+        diagnostics.sort_by_key(|diagnostics| diagnostics.step);
+        diagnostics
+    }
+}
@@ -0,0 +1,129 @@
+struct Particle {
+  pos: vec4<f32>;
+  vel: vec4<f32>;
+  life: f32;
+  charge: f32;
+  mass: f32;
+};
+
+[[block]]
+struct ParticleData {
+  data: [[stride(48)]] array<Particle>;
+};
+
+[[block]]
+struct ReduceParams {
+  particle_count: u32;
+  partial_count: u32;
+};
+
+// Totals over the living particles, in the units of the particle buffer.
+struct Moments {
+  bounds_min: vec3<f32>;
+  alive: u32;
+  bounds_max: vec3<f32>;
+  // `m v^2`, twice the kinetic energy.
+  kinetic: f32;
+  momentum: vec3<f32>;
+  // `q * vel.w`, `vel.w` is the potential at the particle.
+  potential: f32;
+  position_sum: vec3<f32>;
+};
+
+[[block]]
+struct MomentsData {
+  data: [[stride(64)]] array<Moments>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: ReduceParams;
+[[group(0), binding(1)]]
+var<storage, read> particles: ParticleData;
+// One entry per workgroup of `reduce_particles`.
+[[group(0), binding(2)]]
+var<storage, read_write> partials: MomentsData;
+[[group(0), binding(3)]]
+var<storage, read_write> result: MomentsData;
+
+let WORKGROUP_SIZE: u32 = 128u;
+let FLT_MAX: f32 = 3.40282347e38;
+
+var<workgroup> scratch: array<Moments, 128>;
+
+fn empty_moments() -> Moments {
+  var m: Moments;
+  m.bounds_min = vec3<f32>(FLT_MAX);
+  m.alive = 0u;
+  m.bounds_max = vec3<f32>(-FLT_MAX);
+  m.kinetic = 0.;
+  m.momentum = vec3<f32>(0.);
+  m.potential = 0.;
+  m.position_sum = vec3<f32>(0.);
+  return m;
+}
+
+fn combine(a: Moments, b: Moments) -> Moments {
+  var m: Moments;
+  m.bounds_min = min(a.bounds_min, b.bounds_min);
+  m.alive = a.alive + b.alive;
+  m.bounds_max = max(a.bounds_max, b.bounds_max);
+  m.kinetic = a.kinetic + b.kinetic;
+  m.momentum = a.momentum + b.momentum;
+  m.potential = a.potential + b.potential;
+  m.position_sum = a.position_sum + b.position_sum;
+  return m;
+}
+
+// Tree reduction of `scratch`, the total of the workgroup ends up in
+// `scratch[0]`.
+fn reduce_workgroup(local: u32) {
+  for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride = stride / 2u) {
+    workgroupBarrier();
+    if (local < stride) {
+      scratch[local] = combine(scratch[local], scratch[local + stride]);
+    }
+  }
+  workgroupBarrier();
+}
+
+[[stage(compute), workgroup_size(128, 1, 1)]]
+fn reduce_particles(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+    [[builtin(local_invocation_id)]] local_id: vec3<u32>,
+    [[builtin(workgroup_id)]] workgroup_id: vec3<u32>,
+) {
+  var m = empty_moments();
+  if (global_id.x < params.particle_count) {
+    let p = particles.data[global_id.x];
+    // Parked and absorbed particles have a negative lifetime.
+    if (p.life >= 0.) {
+      m.bounds_min = p.pos.xyz;
+      m.alive = 1u;
+      m.bounds_max = p.pos.xyz;
+      m.kinetic = p.mass * dot(p.vel.xyz, p.vel.xyz);
+      m.momentum = p.mass * p.vel.xyz;
+      m.potential = p.charge * p.vel.w;
+      m.position_sum = p.pos.xyz;
+    }
+  }
+  scratch[local_id.x] = m;
+  reduce_workgroup(local_id.x);
+  if (local_id.x == 0u) {
+    partials.data[workgroup_id.x] = scratch[0];
+  }
+}
+
+[[stage(compute), workgroup_size(128, 1, 1)]]
+fn reduce_partials(
+    [[builtin(local_invocation_id)]] local_id: vec3<u32>,
+) {
+  var m = empty_moments();
+  for (var i = local_id.x; i < params.partial_count; i = i + WORKGROUP_SIZE) {
+    m = combine(m, partials.data[i]);
+  }
+  scratch[local_id.x] = m;
+  reduce_workgroup(local_id.x);
+  if (local_id.x == 0u) {
+    result.data[0] = scratch[0];
+  }
+}
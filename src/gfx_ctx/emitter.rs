@@ -0,0 +1,207 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gfx_ctx::Seeding,
+    scene::{Emitter, EmitterShape, VelocityDistribution},
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct GpuEmitter {
+    center: [f32; 3],
+    shape: u32,
+    normal: [f32; 3],
+    radius: f32,
+    half_size: [f32; 3],
+    cumulative_rate: f32,
+    direction: [f32; 3],
+    velocity: u32,
+    speed: [f32; 2],
+    lifetime: [f32; 2],
+    spread: f32,
+    _padding: [f32; 3],
+}
+
+impl GpuEmitter {
+    fn new(emitter: &Emitter, cumulative_rate: f32) -> Self {
+        let mut gpu = Self {
+            normal: Vec3::Z.into(),
+            cumulative_rate,
+            speed: [emitter.speed.start, emitter.speed.end],
+            lifetime: [emitter.lifetime.start, emitter.lifetime.end],
+            ..Default::default()
+        };
+        match emitter.shape {
+            EmitterShape::Point { pos } => {
+                gpu.shape = 0;
+                gpu.center = pos.into();
+            }
+            EmitterShape::Disk {
+                center,
+                normal,
+                radius,
+            } => {
+                gpu.shape = 1;
+                gpu.center = center.into();
+                gpu.normal = normal.normalize().into();
+                gpu.radius = radius;
+            }
+            EmitterShape::Sphere { center, radius } => {
+                gpu.shape = 2;
+                gpu.center = center.into();
+                gpu.radius = radius;
+            }
+            EmitterShape::Plane {
+                center,
+                normal,
+                half_size,
+            } => {
+                gpu.shape = 3;
+                gpu.center = center.into();
+                gpu.normal = normal.normalize().into();
+                gpu.half_size = half_size.extend(0.).into();
+            }
+            EmitterShape::Box { center, half_size } => {
+                gpu.shape = 4;
+                gpu.center = center.into();
+                gpu.half_size = half_size.into();
+            }
+            EmitterShape::Charges { radius } => {
+                gpu.shape = 5;
+                gpu.radius = radius;
+            }
+        }
+        match emitter.velocity {
+            VelocityDistribution::Isotropic => gpu.velocity = 0,
+            VelocityDistribution::Cone { direction, spread } => {
+                gpu.velocity = 1;
+                gpu.direction = direction.normalize().into();
+                gpu.spread = spread;
+            }
+            VelocityDistribution::Normal => gpu.velocity = 2,
+        }
+        gpu
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct EmitParams {
+    emitter_count: u32,
+    budget: u32,
+    total_rate: f32,
+    seeding: u32,
+    seed_radius: f32,
+    _padding: [u32; 3],
+}
+
+/// Emitters uploaded for the respawn path of the simulation shader, and the
+/// per-step budget that turns their rates into particle counts.
+pub struct Emitters {
+    params: EmitParams,
+    seeding: Seeding,
+    accumulator: f32,
+    emitter_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    counter_buffer: wgpu::Buffer,
+}
+
+impl Emitters {
+    pub const MAX_EMITTERS: usize = 64;
+    /// Radius of the spheres field lines start on in [`Seeding::Flux`].
+    const SEED_RADIUS: f32 = 0.03;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let params = EmitParams {
+            emitter_count: 0,
+            budget: 0,
+            total_rate: 0.,
+            seeding: Seeding::Emitters as _,
+            seed_radius: Self::SEED_RADIUS,
+            _padding: [0; 3],
+        };
+        let emitter_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Emitters"),
+            size: (Self::MAX_EMITTERS * std::mem::size_of::<GpuEmitter>()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Emit Params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let counter_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Spawn Counter"),
+            contents: bytemuck::bytes_of(&0u32),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        Self {
+            params,
+            seeding: Seeding::Emitters,
+            accumulator: 0.,
+            emitter_buffer,
+            params_buffer,
+            counter_buffer,
+        }
+    }
+
+    pub fn emitter_buffer(&self) -> &wgpu::Buffer {
+        &self.emitter_buffer
+    }
+
+    pub fn params_buffer(&self) -> &wgpu::Buffer {
+        &self.params_buffer
+    }
+
+    pub fn counter_buffer(&self) -> &wgpu::Buffer {
+        &self.counter_buffer
+    }
+
+    /// Without emitters particles respawn uniformly in the domain.
+    pub fn is_empty(&self) -> bool {
+        self.params.emitter_count == 0
+    }
+
+    pub fn set_emitters(&mut self, queue: &wgpu::Queue, emitters: &[Emitter]) {
+        let mut total_rate = 0.;
+        let emitters: Vec<GpuEmitter> = emitters
+            .iter()
+            .take(Self::MAX_EMITTERS)
+            .map(|emitter| {
+                total_rate += emitter.rate.max(0.);
+                GpuEmitter::new(emitter, total_rate)
+            })
+            .collect();
+        if !emitters.is_empty() {
+            queue.write_buffer(&self.emitter_buffer, 0, bytemuck::cast_slice(&emitters));
+        }
+        self.params.emitter_count = emitters.len() as _;
+        self.params.total_rate = total_rate;
+        self.accumulator = 0.;
+    }
+
+    pub fn seeding(&self) -> Seeding {
+        self.seeding
+    }
+
+    pub fn set_seeding(&mut self, queue: &wgpu::Queue, seeding: Seeding) {
+        self.seeding = seeding;
+        self.params.seeding = seeding as _;
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    /// Grants the particles emitted during a step of `dt`, the fractional
+    /// remainder is carried over to the next step.
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: f32, max_budget: u32) {
+        self.accumulator += self.params.total_rate * dt;
+        let budget = self.accumulator.min(max_budget as f32).floor();
+        self.accumulator -= budget;
+        self.accumulator = self.accumulator.min(1.);
+        self.params.budget = budget as _;
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+        queue.write_buffer(&self.counter_buffer, 0, bytemuck::bytes_of(&0u32));
+    }
+}
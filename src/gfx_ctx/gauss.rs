@@ -0,0 +1,201 @@
+use std::f32::consts::{PI, TAU};
+
+use glam::Vec3;
+
+use crate::gfx_ctx::Probe;
+
+/// Closed surface the flux of the electric field is measured through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GaussSurface {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    /// Axis-aligned box.
+    Box {
+        min: Vec3,
+        max: Vec3,
+    },
+}
+
+impl GaussSurface {
+    pub fn contains(&self, p: Vec3) -> bool {
+        match *self {
+            Self::Sphere { center, radius } => p.distance_squared(center) < radius * radius,
+            Self::Box { min, max } => p.cmpgt(min).all() && p.cmplt(max).all(),
+        }
+    }
+
+    /// Midpoint rule over `resolution` cells along each direction of the
+    /// surface: the probe at the cell centers and the outward area vector of
+    /// each cell, in world units.
+    ///
+    /// The sphere is cut into equal-area cells, uniform in `cos θ` and `φ`
+    /// with twice as many cells along `φ`.
+    pub fn quadrature(&self, resolution: u32) -> (Probe, Vec<Vec3>) {
+        let n = resolution.max(1);
+        let mut points = vec![];
+        let mut areas = vec![];
+        match *self {
+            Self::Sphere { center, radius } => {
+                let area = 4. * PI * radius * radius / (2 * n * n) as f32;
+                for i in 0..n {
+                    let z = 1. - (2 * i + 1) as f32 / n as f32;
+                    let r = (1. - z * z).sqrt();
+                    for j in 0..2 * n {
+                        let phi = (j as f32 + 0.5) / (2 * n) as f32 * TAU;
+                        let normal = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                        points.push(center + normal * radius);
+                        areas.push(normal * area);
+                    }
+                }
+            }
+            Self::Box { min, max } => {
+                let size = max - min;
+                for axis in 0..3 {
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                    let area = size[u] * size[v] / (n * n) as f32;
+                    for (face, sign) in [(min[axis], -1.), (max[axis], 1.)] {
+                        let mut normal = Vec3::ZERO;
+                        normal[axis] = sign;
+                        for i in 0..n {
+                            for j in 0..n {
+                                let mut p = Vec3::ZERO;
+                                p[axis] = face;
+                                p[u] = min[u] + size[u] * (i as f32 + 0.5) / n as f32;
+                                p[v] = min[v] + size[v] * (j as f32 + 0.5) / n as f32;
+                                points.push(p);
+                                areas.push(normal * area);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        (Probe::points(&points), areas)
+    }
+}
+
+/// Flux of the electric field through a [`GaussSurface`] in V m, next to
+/// what Gauss's law asks for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaussFlux {
+    /// Charge inside the surface in coulombs.
+    pub enclosed_charge: f32,
+    /// `Q / ε0` of the enclosed charge.
+    pub expected: f32,
+    /// Flux of the CPU reference field, only off by the quadrature.
+    pub analytic: f32,
+    /// Flux of the field the particles sample, in [`FieldMode::Texture`]
+    /// the baked texture.
+    ///
+    /// [`FieldMode::Texture`]: crate::gfx_ctx::FieldMode::Texture
+    pub sampled: f32,
+}
+
+impl GaussFlux {
+    /// `flux - reference` relative to the expected flux, or absolute in V m
+    /// without any enclosed charge.
+    fn error(&self, flux: f32, reference: f32) -> f32 {
+        if self.expected == 0. {
+            flux - reference
+        } else {
+            (flux - reference) / self.expected
+        }
+    }
+
+    /// Error of the analytic flux, relative unless no charge is enclosed,
+    /// see [`GaussFlux::error`].
+    pub fn analytic_error(&self) -> f32 {
+        self.error(self.analytic, self.expected)
+    }
+
+    pub fn sampled_error(&self) -> f32 {
+        self.error(self.sampled, self.expected)
+    }
+
+    /// Part of the error of the sampled flux the field evaluation adds on top
+    /// of the quadrature, the discretisation error of the grid for the baked
+    /// texture.
+    pub fn discretisation_error(&self) -> f32 {
+        self.error(self.sampled, self.analytic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{get_charge, Charge};
+
+    fn sphere() -> GaussSurface {
+        GaussSurface::Sphere {
+            center: Vec3::new(0.1, -0.2, 0.3),
+            radius: 0.5,
+        }
+    }
+
+    fn cuboid() -> GaussSurface {
+        GaussSurface::Box {
+            min: Vec3::new(-0.5, -0.25, -1.),
+            max: Vec3::new(0.5, 0.75, 0.5),
+        }
+    }
+
+    fn flux(surface: &GaussSurface, charge: Charge) -> f32 {
+        let (probe, areas) = surface.quadrature(32);
+        probe
+            .points
+            .iter()
+            .zip(&areas)
+            .map(|(&p, area)| get_charge(p, charge).dot(*area))
+            .sum()
+    }
+
+    #[test]
+    fn contains() {
+        assert!(sphere().contains(Vec3::new(0.1, -0.2, 0.7)));
+        assert!(!sphere().contains(Vec3::new(0.1, -0.2, 0.9)));
+        assert!(cuboid().contains(Vec3::new(0.4, 0.7, -0.9)));
+        assert!(!cuboid().contains(Vec3::new(0.4, 0.8, -0.9)));
+    }
+
+    #[test]
+    fn sphere_areas() {
+        let (probe, areas) = sphere().quadrature(16);
+        assert_eq!(probe.points.len(), areas.len());
+        let total: f32 = areas.iter().map(|area| area.length()).sum();
+        assert!((total - 4. * PI * 0.25).abs() < 1e-4, "{}", total);
+        assert!(areas.iter().sum::<Vec3>().length() < 1e-4);
+    }
+
+    #[test]
+    fn box_areas() {
+        let (probe, areas) = cuboid().quadrature(16);
+        assert_eq!(probe.points.len(), areas.len());
+        let total: f32 = areas.iter().map(|area| area.length()).sum();
+        let expected = 2. * (1. * 1. + 1. * 1.5 + 1. * 1.5);
+        assert!((total - expected).abs() < 1e-4, "{}", total);
+        assert!(areas.iter().sum::<Vec3>().length() < 1e-4);
+    }
+
+    #[test]
+    fn point_charge_flux() {
+        let inside = Charge {
+            q: 2.,
+            pos: Vec3::new(0.05, -0.1, 0.2),
+        };
+        let outside = Charge {
+            q: 2.,
+            pos: Vec3::new(2., 0., 0.),
+        };
+        for surface in [sphere(), cuboid()] {
+            let enclosed = flux(&surface, inside);
+            assert!(
+                (enclosed / (4. * PI * 2.) - 1.).abs() < 1e-2,
+                "{}",
+                enclosed
+            );
+            assert!(flux(&surface, outside).abs() < 1e-2);
+        }
+    }
+}
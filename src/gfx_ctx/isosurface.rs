@@ -0,0 +1,304 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{gfx_ctx::workgroup_count, scene::Domain};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct MarchingParams {
+    size: [u32; 3],
+    level_count: u32,
+    origin: [f32; 3],
+    max_vertices: u32,
+    spacing: [f32; 3],
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Vertex {
+    pos: [f32; 4],
+    normal: [f32; 4],
+}
+
+impl Vertex {
+    const VERTEX_FORMAT: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4];
+}
+
+/// `wgpu::util::DrawIndirect` with an empty draw.
+const EMPTY_DRAW: [u32; 4] = [0, 1, 0, 0];
+
+/// Equipotential surfaces of the potential in the field texture `w`,
+/// triangulated on the GPU with marching tetrahedra.
+pub struct Isosurface {
+    size: [u32; 3],
+    params: MarchingParams,
+    params_buffer: wgpu::Buffer,
+    level_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+    draw_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    extract_pipeline: wgpu::ComputePipeline,
+    finish_pipeline: wgpu::ComputePipeline,
+}
+
+impl Isosurface {
+    pub const MAX_LEVELS: usize = 16;
+    const MAX_VERTICES: u32 = 1 << 20;
+    const WORKGROUP_SIZE: u32 = 4;
+
+    pub fn new(device: &wgpu::Device, field_texture: &wgpu::TextureView, domain: &Domain) -> Self {
+        let size = domain.resolution;
+        let params = MarchingParams {
+            size,
+            level_count: 0,
+            origin: domain.min.into(),
+            max_vertices: Self::MAX_VERTICES,
+            spacing: domain.spacing().into(),
+            _padding: 0,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Marching Params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let level_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Isosurface Levels"),
+            size: (Self::MAX_LEVELS * std::mem::size_of::<f32>()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Isosurface Vertices"),
+            size: Self::MAX_VERTICES as u64 * std::mem::size_of::<Vertex>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        });
+        let draw_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Isosurface Draw"),
+            contents: bytemuck::cast_slice(&EMPTY_DRAW),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Marching Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Marching Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: level_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(field_texture),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: vertex_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: draw_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("marching.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Marching Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let [extract_pipeline, finish_pipeline] = ["extract", "finish"].map(|entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Marching Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        });
+
+        Self {
+            size,
+            params,
+            params_buffer,
+            level_buffer,
+            vertex_buffer,
+            draw_buffer,
+            bind_group,
+            extract_pipeline,
+            finish_pipeline,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.level_count == 0
+    }
+
+    /// Potentials to extract surfaces for, in the units of the field texture.
+    pub fn set_levels(&mut self, queue: &wgpu::Queue, levels: &[f32]) {
+        let levels = &levels[..levels.len().min(Self::MAX_LEVELS)];
+        if !levels.is_empty() {
+            queue.write_buffer(&self.level_buffer, 0, bytemuck::cast_slice(levels));
+        }
+        self.params.level_count = levels.len() as _;
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+        queue.write_buffer(&self.draw_buffer, 0, bytemuck::cast_slice(&EMPTY_DRAW));
+    }
+
+    /// Retriangulates the surfaces from the current field texture.
+    pub fn extract(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder) {
+        queue.write_buffer(&self.draw_buffer, 0, bytemuck::cast_slice(&EMPTY_DRAW));
+        let [x, y, z] = self
+            .size
+            .map(|len| workgroup_count(len - 1, Self::WORKGROUP_SIZE));
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Extract Isosurface"),
+        });
+        cpass.set_bind_group(0, &self.bind_group, &[]);
+        cpass.set_pipeline(&self.extract_pipeline);
+        cpass.dispatch(x, y, z);
+        cpass.set_pipeline(&self.finish_pipeline);
+        cpass.dispatch(1, 1, 1);
+    }
+
+    /// Draws the surfaces with alpha blending and without depth writes, so it
+    /// goes after the opaque bundles.
+    pub fn draw_command(
+        &self,
+        device: &wgpu::Device,
+        sample_count: u32,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group: &wgpu::BindGroup,
+    ) -> wgpu::RenderBundle {
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("isosurface.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Isosurface Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Isosurface Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as _,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &Vertex::VERTEX_FORMAT,
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+
+        let mut encoder =
+            device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                label: Some("Isosurface Bundle Encoder"),
+                color_formats: &[format],
+                depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_read_only: false,
+                    stencil_read_only: false,
+                }),
+                sample_count,
+                multiview: None,
+            });
+        encoder.set_pipeline(&pipeline);
+        encoder.set_bind_group(0, camera_bind_group, &[]);
+        encoder.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        encoder.draw_indirect(&self.draw_buffer, 0);
+        encoder.finish(&wgpu::RenderBundleDescriptor {
+            label: Some("Draw Isosurface Bundle"),
+        })
+    }
+}
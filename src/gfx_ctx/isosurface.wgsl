@@ -0,0 +1,37 @@
+[[block]]
+struct Camera {
+  view_pos: vec4<f32>;
+  view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]] var<uniform> camera : Camera;
+
+struct VertexInput {
+  [[location(0)]] pos: vec4<f32>;
+  [[location(1)]] normal: vec4<f32>;
+};
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  [[location(0)]] world_position: vec3<f32>;
+  [[location(1)]] normal: vec3<f32>;
+  [[location(2)]] potential: f32;
+};
+
+[[stage(vertex)]]
+fn vs_main(in: VertexInput) -> VertexOutput {
+  let clip_pos = camera.view_proj * vec4<f32>(in.pos.xyz, 1.0);
+  return VertexOutput(clip_pos, in.pos.xyz, in.normal.xyz, in.pos.w);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+  let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+  let n = normalize(in.normal + vec3<f32>(1.0e-6));
+  let shade = 0.3 + 0.7 * abs(dot(n, view_dir));
+
+  var col = vec3<f32>(.8, .2, .1);
+  if (in.potential < 0.) {
+    col = vec3<f32>(.1, .3, .8);
+  }
+  return vec4<f32>(col * shade, 0.25);
+}
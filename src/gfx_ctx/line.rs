@@ -1,6 +1,9 @@
 use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
 use wgpu::{util::DeviceExt, Device};
 
+use crate::scene::Domain;
+
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
 pub struct Vertex {
@@ -19,6 +22,7 @@ macro_rules! v {
     };
 }
 
+/// Edges of the `[-1, 1]` cube, stretched over the domain when drawn.
 #[rustfmt::skip]
 pub const VERTICES: [Vertex; 16] = [
     v!(-1.0, -1.0, -1.0), v!( 1.0, -1.0, -1.0),
@@ -31,8 +35,18 @@ pub const VERTICES: [Vertex; 16] = [
     v!( 1.0,  1.0,  1.0), v!( 1.0,  1.0, -1.0),
 ];
 
+fn bounding_box(domain: &Domain) -> [Vertex; VERTICES.len()] {
+    VERTICES.map(|vertex| {
+        let t = (Vec3::from(vertex.v) + 1.) * 0.5;
+        Vertex {
+            v: (domain.min + t * domain.size()).into(),
+        }
+    })
+}
+
 pub fn draw_lines_command(
     device: &Device,
+    domain: &Domain,
     sample_count: u32,
     format: wgpu::TextureFormat,
     camera_bind_group_layout: &wgpu::BindGroupLayout,
@@ -40,7 +54,7 @@ pub fn draw_lines_command(
 ) -> wgpu::RenderBundle {
     let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(&VERTICES),
+        contents: bytemuck::cast_slice(&bounding_box(domain)),
         usage: wgpu::BufferUsages::VERTEX,
     });
 
@@ -0,0 +1,146 @@
+[[block]]
+struct MarchingParams {
+  size: vec3<u32>;
+  level_count: u32;
+  origin: vec3<f32>;
+  max_vertices: u32;
+  spacing: vec3<f32>;
+};
+
+[[block]]
+struct Levels {
+  data: [[stride(4)]] array<f32>;
+};
+
+// `pos.w` is the potential of the surface, `normal` the interpolated field.
+struct Vertex {
+  pos: vec4<f32>;
+  normal: vec4<f32>;
+};
+
+[[block]]
+struct Vertices {
+  data: [[stride(32)]] array<Vertex>;
+};
+
+// Arguments of the indirect draw, `vertex_count` doubles as the append
+// counter.
+[[block]]
+struct DrawIndirect {
+  vertex_count: atomic<u32>;
+  instance_count: u32;
+  first_vertex: u32;
+  first_instance: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: MarchingParams;
+[[group(0), binding(1)]]
+var<storage, read> levels: Levels;
+[[group(0), binding(2)]]
+var field_texture: texture_3d<f32>;
+[[group(0), binding(3)]]
+var<storage, read_write> vertices: Vertices;
+[[group(0), binding(4)]]
+var<storage, read_write> draw: DrawIndirect;
+
+// Field and potential at the corners of the current cell, indexed by
+// `x | y << 1 | z << 2`.
+var<private> cube: array<vec4<f32>, 8>;
+var<private> cell: vec3<u32>;
+
+// Corners shared by consecutive tetrahedra around the `0-7` diagonal,
+// `1 3 2 6 4 5 1` packed as nibbles.
+let TETRAHEDRA: u32 = 0x01546231u;
+
+fn corner_pos(corner: u32) -> vec3<f32> {
+  let offset = vec3<u32>(corner, corner >> 1u, corner >> 2u) & vec3<u32>(1u);
+  return params.origin + vec3<f32>(cell + offset) * params.spacing;
+}
+
+fn edge(a: u32, b: u32, level: f32) -> Vertex {
+  let t = clamp((level - cube[a].w) / (cube[b].w - cube[a].w), 0., 1.);
+  var v: Vertex;
+  v.pos = vec4<f32>(mix(corner_pos(a), corner_pos(b), t), level);
+  v.normal = vec4<f32>(mix(cube[a].xyz, cube[b].xyz, t), 0.);
+  return v;
+}
+
+fn triangle(a: Vertex, b: Vertex, c: Vertex) {
+  let i = atomicAdd(&draw.vertex_count, 3u);
+  if (i + 3u > params.max_vertices) {
+    return;
+  }
+  vertices.data[i] = a;
+  vertices.data[i + 1u] = b;
+  vertices.data[i + 2u] = c;
+}
+
+fn tetrahedron(corners: vec4<u32>, level: f32) {
+  var inside: array<u32, 4>;
+  var outside: array<u32, 4>;
+  var inside_count = 0u;
+  var outside_count = 0u;
+  for (var k = 0u; k < 4u; k = k + 1u) {
+    let corner = corners[k];
+    if (cube[corner].w < level) {
+      inside[inside_count] = corner;
+      inside_count = inside_count + 1u;
+    } else {
+      outside[outside_count] = corner;
+      outside_count = outside_count + 1u;
+    }
+  }
+
+  if (inside_count == 1u) {
+    triangle(
+      edge(inside[0], outside[0], level),
+      edge(inside[0], outside[1], level),
+      edge(inside[0], outside[2], level),
+    );
+  } elseif (inside_count == 3u) {
+    triangle(
+      edge(outside[0], inside[0], level),
+      edge(outside[0], inside[1], level),
+      edge(outside[0], inside[2], level),
+    );
+  } elseif (inside_count == 2u) {
+    let a = edge(inside[0], outside[0], level);
+    let b = edge(inside[0], outside[1], level);
+    let c = edge(inside[1], outside[1], level);
+    let d = edge(inside[1], outside[0], level);
+    triangle(a, b, c);
+    triangle(a, c, d);
+  }
+}
+
+// Marching tetrahedra, every cell is split into six tetrahedra around its
+// main diagonal, which keeps neighbouring cells crack-free.
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn extract(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  if (any(global_id + 1u >= params.size)) {
+    return;
+  }
+  cell = global_id;
+  for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+    let offset = vec3<u32>(corner, corner >> 1u, corner >> 2u) & vec3<u32>(1u);
+    cube[corner] = textureLoad(field_texture, vec3<i32>(cell + offset), 0);
+  }
+
+  for (var level = 0u; level < params.level_count; level = level + 1u) {
+    for (var i = 0u; i < 6u; i = i + 1u) {
+      let a = (TETRAHEDRA >> (4u * i)) & 0xFu;
+      let b = (TETRAHEDRA >> (4u * (i + 1u))) & 0xFu;
+      tetrahedron(vec4<u32>(0u, a, b, 7u), levels.data[level]);
+    }
+  }
+}
+
+// Drops the triangles that did not fit into the vertex buffer from the draw.
+[[stage(compute), workgroup_size(1, 1, 1)]]
+fn finish() {
+  let count = atomicLoad(&draw.vertex_count);
+  atomicStore(&draw.vertex_count, min(count, params.max_vertices / 3u * 3u));
+}
@@ -0,0 +1,281 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gfx_ctx::{dispatch_size, workgroup_count},
+    scene::Domain,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct TreeParams {
+    particle_count: u32,
+    depth: u32,
+    theta: f32,
+    charge_scale: f32,
+    domain_min: [f32; 3],
+    _padding0: f32,
+    domain_size: [f32; 3],
+    _padding1: f32,
+}
+
+/// Particle–particle Coulomb field through a Barnes–Hut octree, rebuilt from
+/// the particle buffer every step.
+///
+/// The tree is complete down to `DEPTH`, each level a dense grid of monopoles
+/// over the simulation domain, so building it is a deposit into the leaves
+/// followed by one reduction per level.
+pub struct BarnesHut {
+    particle_count: u32,
+    params: TreeParams,
+    params_buffer: wgpu::Buffer,
+    interaction_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    level_bind_group: wgpu::BindGroup,
+    deposit_pipeline: wgpu::ComputePipeline,
+    resolve_pipeline: wgpu::ComputePipeline,
+    reduce_pipeline: wgpu::ComputePipeline,
+    evaluate_pipeline: wgpu::ComputePipeline,
+}
+
+impl BarnesHut {
+    /// Levels below the root, the leaves form a 64³ grid.
+    const DEPTH: u32 = 6;
+    /// Opening angle, nodes seen under a larger one are split.
+    const THETA: f32 = 0.5;
+    const WORKGROUP_SIZE: u32 = 4;
+    /// Offset between the per-level uniforms, the minimal dynamic offset
+    /// alignment.
+    const LEVEL_STRIDE: u64 = 256;
+
+    pub fn new(
+        device: &wgpu::Device,
+        particle_buffer: &wgpu::Buffer,
+        particle_count: u32,
+        domain: &Domain,
+    ) -> Self {
+        let params = TreeParams {
+            particle_count,
+            depth: Self::DEPTH,
+            theta: Self::THETA,
+            charge_scale: 1.,
+            domain_min: domain.min.into(),
+            _padding0: 0.,
+            domain_size: domain.size().into(),
+            _padding1: 0.,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tree Params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let mut levels = vec![0; (Self::DEPTH as u64 * Self::LEVEL_STRIDE) as usize / 4];
+        for level in 0..Self::DEPTH {
+            levels[(level as u64 * Self::LEVEL_STRIDE) as usize / 4] = level;
+        }
+        let level_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tree Levels"),
+            contents: bytemuck::cast_slice(&levels),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let leaf_count = 1u64 << (3 * Self::DEPTH);
+        let node_count = ((1u64 << (3 * (Self::DEPTH + 1))) - 1) / 7;
+        let leaf_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tree Leaves"),
+            size: leaf_count * 5 * std::mem::size_of::<i32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let node_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tree Nodes"),
+            size: node_count * 8 * std::mem::size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let interaction_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Interaction"),
+            size: particle_count as u64 * 4 * std::mem::size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tree Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tree Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: leaf_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: node_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: interaction_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let level_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Tree Level Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<u32>() as _),
+                    },
+                    count: None,
+                }],
+            });
+        let level_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tree Level Bind Group"),
+            layout: &level_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &level_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<u32>() as _),
+                }),
+            }],
+        });
+
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("nbody.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tree Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, &level_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let [deposit_pipeline, resolve_pipeline, reduce_pipeline, evaluate_pipeline] =
+            ["deposit", "resolve", "reduce", "evaluate"].map(|entry_point| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Tree Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            });
+
+        Self {
+            particle_count,
+            params,
+            params_buffer,
+            interaction_buffer,
+            bind_group,
+            level_bind_group,
+            deposit_pipeline,
+            resolve_pipeline,
+            reduce_pipeline,
+            evaluate_pipeline,
+        }
+    }
+
+    /// Per-particle field of all the other particles, `vec4` with an unused
+    /// `w`.
+    pub fn interaction_buffer(&self) -> &wgpu::Buffer {
+        &self.interaction_buffer
+    }
+
+    /// Charge of the particles relative to the scene charges.
+    pub fn set_charge_scale(&mut self, queue: &wgpu::Queue, scale: f32) {
+        if self.params.charge_scale != scale {
+            self.params.charge_scale = scale;
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+        }
+    }
+
+    /// Rebuilds the tree from the current particle positions and evaluates
+    /// the interaction field of every particle.
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Barnes-Hut"),
+        });
+        cpass.set_bind_group(0, &self.bind_group, &[]);
+        cpass.set_bind_group(1, &self.level_bind_group, &[0]);
+
+        cpass.set_pipeline(&self.deposit_pipeline);
+        cpass.dispatch(dispatch_size(self.particle_count), 1, 1);
+
+        let leaves = workgroup_count(1 << Self::DEPTH, Self::WORKGROUP_SIZE);
+        cpass.set_pipeline(&self.resolve_pipeline);
+        cpass.dispatch(leaves, leaves, leaves);
+
+        cpass.set_pipeline(&self.reduce_pipeline);
+        for level in (0..Self::DEPTH).rev() {
+            let offset = (level as u64 * Self::LEVEL_STRIDE) as u32;
+            cpass.set_bind_group(1, &self.level_bind_group, &[offset]);
+            let size = workgroup_count(1 << level, Self::WORKGROUP_SIZE);
+            cpass.dispatch(size, size, size);
+        }
+
+        cpass.set_pipeline(&self.evaluate_pipeline);
+        cpass.dispatch(dispatch_size(self.particle_count), 1, 1);
+    }
+}
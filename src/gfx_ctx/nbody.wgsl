@@ -0,0 +1,218 @@
+struct Particle {
+  pos: vec4<f32>;
+  vel: vec4<f32>;
+  life: f32;
+  charge: f32;
+  mass: f32;
+};
+
+[[block]]
+struct ParticleData {
+  data: [[stride(48)]] array<Particle>;
+};
+
+[[block]]
+struct TreeParams {
+  particle_count: u32;
+  depth: u32;
+  theta: f32;
+  charge_scale: f32;
+  domain_min: vec3<f32>;
+  domain_size: vec3<f32>;
+};
+
+[[block]]
+struct LevelParams {
+  level: u32;
+};
+
+// Fixed-point `|q| * pos` and `|q|`, `q` of the particles in every leaf.
+[[block]]
+struct Leaves {
+  data: [[stride(4)]] array<atomic<i32>>;
+};
+
+// Monopole of an octree node. The expansion center is weighted by the charge
+// magnitude so it stays inside the node when charges of both signs cancel,
+// `moment` is in coordinates normalized to the domain.
+struct Node {
+  moment: vec3<f32>;
+  weight: f32;
+  charge: f32;
+};
+
+[[block]]
+struct Nodes {
+  data: [[stride(32)]] array<Node>;
+};
+
+[[block]]
+struct Interaction {
+  data: [[stride(16)]] array<vec4<f32>>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: TreeParams;
+[[group(0), binding(1)]]
+var<storage, read> particles: ParticleData;
+[[group(0), binding(2)]]
+var<storage, read_write> leaves: Leaves;
+[[group(0), binding(3)]]
+var<storage, read_write> nodes: Nodes;
+[[group(0), binding(4)]]
+var<storage, read_write> interaction: Interaction;
+[[group(1), binding(0)]]
+var<uniform> level_params: LevelParams;
+
+let FIXED_POINT_SCALE: f32 = 256.;
+let SOFTENING: f32 = 1.0e-4;
+
+// Nodes are stored level after level, each level as a dense grid.
+fn node_index(level: u32, c: vec3<u32>) -> u32 {
+  let n = 1u << level;
+  let first = ((1u << (3u * level)) - 1u) / 7u;
+  return first + c.x + n * (c.y + n * c.z);
+}
+
+fn normalized(p: vec3<f32>) -> vec3<f32> {
+  return (p - params.domain_min) / params.domain_size;
+}
+
+fn leaf_coords(p: vec3<f32>) -> vec3<u32> {
+  let n = f32(1u << params.depth);
+  return vec3<u32>(clamp(floor(normalized(p) * n), vec3<f32>(0.), vec3<f32>(n - 1.)));
+}
+
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn deposit(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  if (id >= params.particle_count) {
+    return;
+  }
+  let particle = particles.data[id];
+  if (particle.life <= 0.) {
+    return;
+  }
+
+  let p = particle.pos.xyz;
+  let c = leaf_coords(p);
+  let n = 1u << params.depth;
+  let i = 5u * (c.x + n * (c.y + n * c.z));
+  let weight = abs(particle.charge) * FIXED_POINT_SCALE;
+  let moment = vec3<i32>(round(normalized(p) * weight));
+  // The WGSL front end only takes calls to user functions as statements,
+  // so the previous values are stored and dropped.
+  var ignored: i32;
+  ignored = atomicAdd(&leaves.data[i], moment.x);
+  ignored = atomicAdd(&leaves.data[i + 1u], moment.y);
+  ignored = atomicAdd(&leaves.data[i + 2u], moment.z);
+  ignored = atomicAdd(&leaves.data[i + 3u], i32(round(weight)));
+  ignored = atomicAdd(&leaves.data[i + 4u], i32(round(particle.charge * FIXED_POINT_SCALE)));
+}
+
+// Converts the leaf accumulators into the deepest level of nodes and clears
+// them for the next step.
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn resolve(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let n = 1u << params.depth;
+  if (any(global_id >= vec3<u32>(n))) {
+    return;
+  }
+  let i = 5u * (global_id.x + n * (global_id.y + n * global_id.z));
+  var node: Node;
+  node.moment = vec3<f32>(
+    f32(atomicLoad(&leaves.data[i])),
+    f32(atomicLoad(&leaves.data[i + 1u])),
+    f32(atomicLoad(&leaves.data[i + 2u])),
+  ) / FIXED_POINT_SCALE;
+  node.weight = f32(atomicLoad(&leaves.data[i + 3u])) / FIXED_POINT_SCALE;
+  node.charge = f32(atomicLoad(&leaves.data[i + 4u])) / FIXED_POINT_SCALE;
+  nodes.data[node_index(params.depth, global_id)] = node;
+
+  for (var j = 0u; j < 5u; j = j + 1u) {
+    atomicStore(&leaves.data[i + j], 0);
+  }
+}
+
+// Sums the eight children of every node of `level_params.level`.
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn reduce(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let level = level_params.level;
+  if (any(global_id >= vec3<u32>(1u << level))) {
+    return;
+  }
+  var node: Node;
+  node.moment = vec3<f32>(0.);
+  node.weight = 0.;
+  node.charge = 0.;
+  for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+    let offset = vec3<u32>(corner, corner >> 1u, corner >> 2u) & vec3<u32>(1u);
+    let child = nodes.data[node_index(level + 1u, global_id * 2u + offset)];
+    node.moment = node.moment + child.moment;
+    node.weight = node.weight + child.weight;
+    node.charge = node.charge + child.charge;
+  }
+  nodes.data[node_index(level, global_id)] = node;
+}
+
+// Walks the tree from the root, opening nodes that contain the particle or
+// look larger than `theta` from it.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn evaluate(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  if (id >= params.particle_count) {
+    return;
+  }
+  let particle = particles.data[id];
+  let p = particle.pos.xyz;
+  let leaf = leaf_coords(p);
+
+  // At most 7 siblings are left behind per level.
+  var stack: array<vec4<u32>, 64>;
+  stack[0] = vec4<u32>(0u);
+  var top = 1u;
+  var field = vec3<f32>(0.);
+  loop {
+    if (top == 0u) {
+      break;
+    }
+    top = top - 1u;
+    let entry = stack[top];
+    let c = entry.xyz;
+    let level = entry.w;
+
+    var node = nodes.data[node_index(level, c)];
+    let own = all((leaf >> vec3<u32>(params.depth - level)) == c);
+    if (own && level == params.depth) {
+      node.moment = node.moment - abs(particle.charge) * normalized(p);
+      node.weight = node.weight - abs(particle.charge);
+      node.charge = node.charge - particle.charge;
+    }
+    if (node.weight < 0.5 / FIXED_POINT_SCALE) {
+      continue;
+    }
+
+    let r = p - (params.domain_min + params.domain_size * node.moment / node.weight);
+    let d2 = dot(r, r);
+    let extent = params.domain_size;
+    let size = max(extent.x, max(extent.y, extent.z)) / f32(1u << level);
+    if (level < params.depth && (own || size * size > params.theta * params.theta * d2)) {
+      for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+        let offset = vec3<u32>(corner, corner >> 1u, corner >> 2u) & vec3<u32>(1u);
+        stack[top] = vec4<u32>(c * 2u + offset, level + 1u);
+        top = top + 1u;
+      }
+      continue;
+    }
+    field = field + r * (node.charge / pow(d2 + SOFTENING, 1.5));
+  }
+  interaction.data[id] = vec4<f32>(field * params.charge_scale, 0.);
+}
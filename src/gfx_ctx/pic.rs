@@ -0,0 +1,160 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gfx_ctx::{dispatch_size, workgroup_count},
+    scene::Domain,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct DepositParams {
+    size: [u32; 3],
+    particle_count: u32,
+    origin: [f32; 3],
+    fixed_point_scale: f32,
+    spacing: [f32; 3],
+    _padding: f32,
+}
+
+/// Particle charge deposited onto the field grid, the source term of the
+/// particle-in-cell loop.
+pub struct SpaceCharge {
+    size: [u32; 3],
+    particle_count: u32,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    clear_pipeline: wgpu::ComputePipeline,
+    deposit_pipeline: wgpu::ComputePipeline,
+}
+
+impl SpaceCharge {
+    /// Fixed-point units per unit of particle charge. Leaves room for about
+    /// 8 million unit charges on a single node before overflowing.
+    pub const FIXED_POINT_SCALE: f32 = 256.;
+    const WORKGROUP_SIZE: u32 = 4;
+
+    pub fn new(
+        device: &wgpu::Device,
+        particle_buffer: &wgpu::Buffer,
+        particle_count: u32,
+        domain: &Domain,
+    ) -> Self {
+        let size = domain.resolution;
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Deposit Params"),
+            contents: bytemuck::bytes_of(&DepositParams {
+                size,
+                particle_count,
+                origin: domain.min.into(),
+                fixed_point_scale: Self::FIXED_POINT_SCALE,
+                spacing: domain.spacing().into(),
+                _padding: 0.,
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Space Charge"),
+            size: (size.iter().product::<u32>() as usize * std::mem::size_of::<i32>()) as _,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Deposit Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Deposit Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("pic.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Deposit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let [clear_pipeline, deposit_pipeline] = ["clear", "deposit"].map(|entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Deposit Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        });
+
+        Self {
+            size,
+            particle_count,
+            buffer,
+            bind_group,
+            clear_pipeline,
+            deposit_pipeline,
+        }
+    }
+
+    /// Fixed-point `i32` charge per grid node.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn deposit(&self, encoder: &mut wgpu::CommandEncoder) {
+        let [x, y, z] = self
+            .size
+            .map(|len| workgroup_count(len, Self::WORKGROUP_SIZE));
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Deposit Space Charge"),
+        });
+        cpass.set_bind_group(0, &self.bind_group, &[]);
+        cpass.set_pipeline(&self.clear_pipeline);
+        cpass.dispatch(x, y, z);
+        cpass.set_pipeline(&self.deposit_pipeline);
+        cpass.dispatch(dispatch_size(self.particle_count), 1, 1);
+    }
+}
@@ -0,0 +1,81 @@
+struct Particle {
+  pos: vec4<f32>;
+  vel: vec4<f32>;
+  life: f32;
+  charge: f32;
+  mass: f32;
+};
+
+[[block]]
+struct ParticleData {
+  data: [[stride(48)]] array<Particle>;
+};
+
+[[block]]
+struct DepositParams {
+  size: vec3<u32>;
+  particle_count: u32;
+  origin: vec3<f32>;
+  fixed_point_scale: f32;
+  spacing: vec3<f32>;
+};
+
+// Fixed-point charge per grid node, floats have no atomic add.
+[[block]]
+struct SpaceCharge {
+  data: [[stride(4)]] array<atomic<i32>>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: DepositParams;
+[[group(0), binding(1)]]
+var<storage, read> particles: ParticleData;
+[[group(0), binding(2)]]
+var<storage, read_write> space_charge: SpaceCharge;
+
+fn index(c: vec3<u32>) -> u32 {
+  return c.x + params.size.x * (c.y + params.size.y * c.z);
+}
+
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn clear(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  if (any(global_id >= params.size)) {
+    return;
+  }
+  atomicStore(&space_charge.data[index(global_id)], 0);
+}
+
+// Spreads the particle charge onto the 8 surrounding nodes with cloud-in-cell
+// weights, the same the field texture is sampled back with.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn deposit(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  if (id >= params.particle_count) {
+    return;
+  }
+  let particle = particles.data[id];
+  if (particle.life <= 0.) {
+    return;
+  }
+
+  let f = (particle.pos.xyz - params.origin) / params.spacing;
+  let base = floor(f);
+  let w = f - base;
+  let q = particle.charge * params.fixed_point_scale;
+  for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+    let offset = vec3<f32>(vec3<u32>(corner, corner >> 1u, corner >> 2u) & vec3<u32>(1u));
+    let node = vec3<i32>(base + offset);
+    if (any(node < vec3<i32>(0)) || any(node >= vec3<i32>(params.size))) {
+      continue;
+    }
+    let weight = 1. - abs(offset - w);
+    let amount = i32(round(q * weight.x * weight.y * weight.z));
+    // The WGSL front end only takes calls to user functions as statements,
+    // so the previous value is bound and dropped.
+    let ignored = atomicAdd(&space_charge.data[index(vec3<u32>(node))], amount);
+  }
+}
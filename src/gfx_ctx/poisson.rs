@@ -0,0 +1,412 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gfx_ctx::workgroup_count,
+    scene::{Boundaries, Boundary, Charge, ChargeDistribution, Dielectric, Domain, Electrode},
+};
+
+impl Boundary {
+    fn kind(self) -> u32 {
+        match self {
+            Self::Dirichlet(_) => 0,
+            Self::Neumann => 1,
+        }
+    }
+
+    fn value(self) -> f32 {
+        match self {
+            Self::Dirichlet(value) => value,
+            Self::Neumann => 0.,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct PoissonParams {
+    size: [u32; 3],
+    omega: f32,
+    spacing: [f32; 3],
+    source_scale: f32,
+    lower_kind: [u32; 3],
+    _padding0: u32,
+    upper_kind: [u32; 3],
+    _padding1: u32,
+    lower_value: [f32; 3],
+    _padding2: f32,
+    upper_value: [f32; 3],
+    space_charge_scale: f32,
+}
+
+impl PoissonParams {
+    fn new(domain: &Domain, omega: f32, boundaries: &Boundaries, space_charge_scale: f32) -> Self {
+        let lower = [boundaries[0], boundaries[2], boundaries[4]];
+        let upper = [boundaries[1], boundaries[3], boundaries[5]];
+        Self {
+            size: domain.resolution,
+            omega,
+            spacing: domain.spacing().into(),
+            // Gaussian units of the Coulomb bake, `lap(phi) = -4pi rho`.
+            source_scale: 4. * std::f32::consts::PI,
+            lower_kind: lower.map(Boundary::kind),
+            _padding0: 0,
+            upper_kind: upper.map(Boundary::kind),
+            _padding1: 0,
+            lower_value: lower.map(Boundary::value),
+            _padding2: 0.,
+            upper_value: upper.map(Boundary::value),
+            space_charge_scale,
+        }
+    }
+}
+
+/// Spreads point charges onto the grid nodes with cloud-in-cell weights, the
+/// distributions are split into point charges first.
+pub fn charge_density(
+    charges: &[Charge],
+    distributions: &[ChargeDistribution],
+    domain: &Domain,
+) -> Vec<f32> {
+    let [width, height, depth] = domain.resolution;
+    let spacing = domain.spacing();
+    let cell_volume = spacing.x * spacing.y * spacing.z;
+    let mut density = vec![0.; (width * height * depth) as usize];
+
+    let distributed: Vec<Charge> = distributions
+        .iter()
+        .flat_map(|distribution| distribution.point_charges(domain))
+        .collect();
+    for charge in charges.iter().chain(&distributed) {
+        let f = domain.grid_coords(charge.pos);
+        let base = f.floor();
+        let w = f - base;
+        for corner in 0..8 {
+            let offset = Vec3::new(
+                (corner & 1) as f32,
+                ((corner >> 1) & 1) as f32,
+                ((corner >> 2) & 1) as f32,
+            );
+            let node = base + offset;
+            if node.min_element() < 0.
+                || node.x >= width as f32
+                || node.y >= height as f32
+                || node.z >= depth as f32
+            {
+                continue;
+            }
+            let weight = Vec3::ONE - (offset - w).abs();
+            let [x, y, z] = [node.x as u32, node.y as u32, node.z as u32];
+            density[(x + width * (y + height * z)) as usize] +=
+                charge.q * weight.x * weight.y * weight.z / cell_volume;
+        }
+    }
+    density
+}
+
+/// Marks the grid nodes covered by electrodes, `[1, potential]` for fixed
+/// nodes and `[0, 0]` for free ones. Surfaces are thickened by half a cell so
+/// thin conductors don't slip between the nodes.
+pub fn electrode_mask(electrodes: &[Electrode], domain: &Domain) -> Vec<[f32; 2]> {
+    let [width, height, depth] = domain.resolution;
+    let tolerance = domain.spacing().max_element() * 0.5;
+    (0..width * height * depth)
+        .map(|id| {
+            let [x, y, z] = [id % width, (id / width) % height, id / (width * height)];
+            let p = domain.node_position([x, y, z]);
+            electrodes
+                .iter()
+                .find(|electrode| electrode.shape.distance(p) <= tolerance)
+                .map_or([0., 0.], |electrode| [1., electrode.potential])
+        })
+        .collect()
+}
+
+/// Relative permittivity of every grid node, later dielectrics override the
+/// earlier ones where they overlap. Panics unless every permittivity is
+/// positive, the solver divides by it.
+pub fn permittivity(dielectrics: &[Dielectric], domain: &Domain) -> Vec<f32> {
+    for dielectric in dielectrics {
+        assert!(
+            dielectric.permittivity > 0.,
+            "permittivity must be positive, got {}",
+            dielectric.permittivity
+        );
+    }
+    let [width, height, depth] = domain.resolution;
+    (0..width * height * depth)
+        .map(|id| {
+            let [x, y, z] = [id % width, (id / width) % height, id / (width * height)];
+            let p = domain.node_position([x, y, z]);
+            dielectrics
+                .iter()
+                .rev()
+                .find(|dielectric| dielectric.shape.contains(p))
+                .map_or(1., |dielectric| dielectric.permittivity)
+        })
+        .collect()
+}
+
+/// Red-black SOR solver for `div(eps grad(phi)) = -4pi rho` on the field
+/// grid, writing `E = -grad(phi)` and `phi` into the field texture. `rho` is the charge
+/// density volume plus the scaled particle space charge.
+pub struct PoissonSolver {
+    domain: Domain,
+    params: PoissonParams,
+    params_buffer: wgpu::Buffer,
+    density_buffer: wgpu::Buffer,
+    electrode_buffer: wgpu::Buffer,
+    permittivity_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    red_pipeline: wgpu::ComputePipeline,
+    black_pipeline: wgpu::ComputePipeline,
+    gradient_pipeline: wgpu::ComputePipeline,
+}
+
+impl PoissonSolver {
+    const WORKGROUP_SIZE: u32 = 4;
+    const OMEGA: f32 = 1.9;
+
+    pub fn new(
+        device: &wgpu::Device,
+        field_texture: &wgpu::TextureView,
+        space_charge: &wgpu::Buffer,
+        domain: &Domain,
+    ) -> Self {
+        let params = PoissonParams::new(domain, Self::OMEGA, &[Boundary::Dirichlet(0.); 6], 0.);
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Poisson Params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let volume_size = (domain.node_count() as usize * std::mem::size_of::<f32>()) as _;
+        let density_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Charge Density"),
+            size: volume_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let electrode_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Electrodes"),
+            size: volume_size * 2,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let permittivity_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Permittivity"),
+            contents: bytemuck::cast_slice(&permittivity(&[], domain)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let potential_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Potential"),
+            size: volume_size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Poisson Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Poisson Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: density_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: potential_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(field_texture),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: electrode_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: permittivity_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: space_charge.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("poisson.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Poisson Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let [red_pipeline, black_pipeline, gradient_pipeline] =
+            ["relax_red", "relax_black", "gradient"].map(|entry_point| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Poisson Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            });
+
+        Self {
+            domain: *domain,
+            params,
+            params_buffer,
+            density_buffer,
+            electrode_buffer,
+            permittivity_buffer,
+            bind_group,
+            red_pipeline,
+            black_pipeline,
+            gradient_pipeline,
+        }
+    }
+
+    pub fn set_density(&self, queue: &wgpu::Queue, density: &[f32]) {
+        queue.write_buffer(&self.density_buffer, 0, bytemuck::cast_slice(density));
+    }
+
+    /// Per-node electrode mask, shared with the particle kernels so they can
+    /// absorb particles hitting a conductor.
+    pub fn electrode_buffer(&self) -> &wgpu::Buffer {
+        &self.electrode_buffer
+    }
+
+    pub fn set_electrodes(&self, queue: &wgpu::Queue, electrodes: &[Electrode]) {
+        queue.write_buffer(
+            &self.electrode_buffer,
+            0,
+            bytemuck::cast_slice(&electrode_mask(electrodes, &self.domain)),
+        );
+    }
+
+    pub fn set_dielectrics(&self, queue: &wgpu::Queue, dielectrics: &[Dielectric]) {
+        queue.write_buffer(
+            &self.permittivity_buffer,
+            0,
+            bytemuck::cast_slice(&permittivity(dielectrics, &self.domain)),
+        );
+    }
+
+    pub fn set_boundaries(&mut self, queue: &wgpu::Queue, boundaries: &Boundaries) {
+        self.params = PoissonParams::new(
+            &self.domain,
+            self.params.omega,
+            boundaries,
+            self.params.space_charge_scale,
+        );
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    /// Charge of one fixed-point unit of the space charge buffer, `0` leaves
+    /// the particles out of the solve.
+    pub fn set_space_charge_scale(&mut self, queue: &wgpu::Queue, scale: f32) {
+        let spacing = self.domain.spacing();
+        self.params.space_charge_scale = scale / (spacing.x * spacing.y * spacing.z);
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    /// Runs `sweeps` red-black iterations, warm started from the previous
+    /// potential, and differentiates the result into the field texture.
+    pub fn solve(&self, encoder: &mut wgpu::CommandEncoder, sweeps: u32) {
+        let [x, y, z] = self
+            .domain
+            .resolution
+            .map(|len| workgroup_count(len, Self::WORKGROUP_SIZE));
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Poisson Solve"),
+        });
+        cpass.set_bind_group(0, &self.bind_group, &[]);
+        for _ in 0..sweeps {
+            cpass.set_pipeline(&self.red_pipeline);
+            cpass.dispatch(x, y, z);
+            cpass.set_pipeline(&self.black_pipeline);
+            cpass.dispatch(x, y, z);
+        }
+        cpass.set_pipeline(&self.gradient_pipeline);
+        cpass.dispatch(x, y, z);
+    }
+}
@@ -0,0 +1,156 @@
+[[block]]
+struct PoissonParams {
+  size: vec3<u32>;
+  omega: f32;
+  spacing: vec3<f32>;
+  source_scale: f32;
+  lower_kind: vec3<u32>;
+  upper_kind: vec3<u32>;
+  lower_value: vec3<f32>;
+  upper_value: vec3<f32>;
+  space_charge_scale: f32;
+};
+
+[[block]]
+struct Volume {
+  data: [[stride(4)]] array<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: PoissonParams;
+[[group(0), binding(1)]]
+var<storage, read> density: Volume;
+[[group(0), binding(2)]]
+var<storage, read_write> potential: Volume;
+[[group(0), binding(3)]]
+var field_texture: texture_storage_3d<rgba32float, write>;
+
+// `x` is 1 for nodes inside a conductor, `y` the potential it is held at.
+[[block]]
+struct Electrodes {
+  data: [[stride(8)]] array<vec2<f32>>;
+};
+
+[[group(0), binding(4)]]
+var<storage, read> electrodes: Electrodes;
+[[group(0), binding(5)]]
+var<storage, read> permittivity: Volume;
+
+// Fixed-point particle charge deposited by the particle-in-cell loop.
+[[block]]
+struct SpaceCharge {
+  data: [[stride(4)]] array<i32>;
+};
+
+[[group(0), binding(6)]]
+var<storage, read> space_charge: SpaceCharge;
+
+let BOUNDARY_DIRICHLET: u32 = 0u;
+
+fn index(c: vec3<u32>) -> u32 {
+  return c.x + params.size.x * (c.y + params.size.y * c.z);
+}
+
+fn boundary(kind: u32, value: f32, inner: f32) -> f32 {
+  if (kind == BOUNDARY_DIRICHLET) {
+    return value;
+  }
+  // Neumann, mirror the inner cell so the normal derivative vanishes.
+  return inner;
+}
+
+// Potential of the cell `c`, cells outside of the grid are ghost cells
+// resolved from the boundary conditions and `inner`.
+fn potential_at(c: vec3<i32>, inner: f32) -> f32 {
+  let size = vec3<i32>(params.size);
+  if (c.x < 0) { return boundary(params.lower_kind.x, params.lower_value.x, inner); }
+  if (c.y < 0) { return boundary(params.lower_kind.y, params.lower_value.y, inner); }
+  if (c.z < 0) { return boundary(params.lower_kind.z, params.lower_value.z, inner); }
+  if (c.x >= size.x) { return boundary(params.upper_kind.x, params.upper_value.x, inner); }
+  if (c.y >= size.y) { return boundary(params.upper_kind.y, params.upper_value.y, inner); }
+  if (c.z >= size.z) { return boundary(params.upper_kind.z, params.upper_value.z, inner); }
+  return potential.data[index(vec3<u32>(c))];
+}
+
+let DX: vec3<i32> = vec3<i32>(1, 0, 0);
+let DY: vec3<i32> = vec3<i32>(0, 1, 0);
+let DZ: vec3<i32> = vec3<i32>(0, 0, 1);
+
+// Permittivity on the face between the cell `i` and its neighbour `c`, the
+// harmonic mean keeps the normal component of D continuous across interfaces.
+// Ghost cells take the permittivity of `i`.
+fn face_permittivity(i: u32, c: vec3<i32>) -> f32 {
+  let eps = permittivity.data[i];
+  if (any(c < vec3<i32>(0)) || any(c >= vec3<i32>(params.size))) {
+    return eps;
+  }
+  let other = permittivity.data[index(vec3<u32>(c))];
+  return 2. * eps * other / (eps + other);
+}
+
+// One successive over-relaxation update of the 7-point variable-coefficient
+// Laplacian, `div(eps grad(phi)) = -source_scale * rho`.
+fn relax(c: vec3<u32>, parity: u32) {
+  if (any(c >= params.size) || (c.x + c.y + c.z) % 2u != parity) {
+    return;
+  }
+  let i = index(c);
+  let electrode = electrodes.data[i];
+  if (electrode.x > 0.5) {
+    potential.data[i] = electrode.y;
+    return;
+  }
+
+  let p = vec3<i32>(c);
+  let phi = potential.data[i];
+  let inv_h2 = 1. / (params.spacing * params.spacing);
+
+  var sum = 0.;
+  var diagonal = 0.;
+  for (var axis = 0u; axis < 3u; axis = axis + 1u) {
+    var d = vec3<i32>(0);
+    d[axis] = 1;
+    let lower = face_permittivity(i, p - d) * inv_h2[axis];
+    let upper = face_permittivity(i, p + d) * inv_h2[axis];
+    sum = sum + potential_at(p - d, phi) * lower + potential_at(p + d, phi) * upper;
+    diagonal = diagonal + lower + upper;
+  }
+  let rho = density.data[i] + f32(space_charge.data[i]) * params.space_charge_scale;
+  let gauss_seidel = (sum + params.source_scale * rho) / diagonal;
+
+  potential.data[i] = mix(phi, gauss_seidel, params.omega);
+}
+
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn relax_red(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  relax(global_id, 0u);
+}
+
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn relax_black(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  relax(global_id, 1u);
+}
+
+// Central differences of the potential, `E = -grad(phi)`, stored next to
+// the potential itself.
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn gradient(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  if (any(global_id >= params.size)) {
+    return;
+  }
+  let p = vec3<i32>(global_id);
+  let phi = potential.data[index(global_id)];
+
+  let diff = vec3<f32>(
+    potential_at(p + DX, phi) - potential_at(p - DX, phi),
+    potential_at(p + DY, phi) - potential_at(p - DY, phi),
+    potential_at(p + DZ, phi) - potential_at(p - DZ, phi),
+  );
+  textureStore(field_texture, p, vec4<f32>(-diff / (2. * params.spacing), phi));
+}
@@ -0,0 +1,272 @@
+use std::io::{self, Write};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+
+use crate::gfx_ctx::dispatch_size;
+
+/// Points to evaluate the field at, optionally spread along a polyline.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Probe {
+    pub points: Vec<Vec3>,
+    /// Arc length of each point along the polyline, zero for scattered
+    /// points.
+    pub distances: Vec<f32>,
+}
+
+impl Probe {
+    pub fn points(points: &[Vec3]) -> Self {
+        Self {
+            points: points.to_vec(),
+            distances: vec![0.; points.len()],
+        }
+    }
+
+    /// `count` points evenly spaced by arc length from the first vertex of
+    /// the polyline to the last.
+    pub fn polyline(vertices: &[Vec3], count: usize) -> Self {
+        let lengths: Vec<f32> = vertices
+            .windows(2)
+            .map(|segment| segment[0].distance(segment[1]))
+            .collect();
+        let total: f32 = lengths.iter().sum();
+        let mut probe = Self::default();
+        if vertices.is_empty() {
+            return probe;
+        }
+        let (mut segment, mut start) = (0, 0.);
+        for i in 0..count {
+            let distance = total * i as f32 / (count - 1).max(1) as f32;
+            while segment + 1 < lengths.len() && distance > start + lengths[segment] {
+                start += lengths[segment];
+                segment += 1;
+            }
+            let point = match lengths.get(segment) {
+                Some(&length) if length > 0. => vertices[segment]
+                    .lerp(vertices[segment + 1], ((distance - start) / length).min(1.)),
+                _ => vertices[segment],
+            };
+            probe.points.push(point);
+            probe.distances.push(distance);
+        }
+        probe
+    }
+}
+
+/// Field in V/m and potential in volts at a probe point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProbeSample {
+    pub distance: f32,
+    pub pos: Vec3,
+    pub field: Vec3,
+    pub potential: f32,
+}
+
+impl ProbeSample {
+    pub const CSV_HEADER: &'static str = "distance,x,y,z,field_x,field_y,field_z,field,potential";
+
+    /// Writes one line in the column order of [`ProbeSample::CSV_HEADER`].
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        let Self {
+            pos: p, field: e, ..
+        } = self;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{}",
+            self.distance,
+            p.x,
+            p.y,
+            p.z,
+            e.x,
+            e.y,
+            e.z,
+            e.length(),
+            self.potential,
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ProbeParams {
+    point_count: u32,
+    _padding: [u32; 3],
+}
+
+/// Runs `probe_field` of `simulation.wgsl` over a batch of points, so they
+/// see the field exactly as the particles do, and reads the result back.
+pub struct FieldProbe {
+    params_buffer: wgpu::Buffer,
+    point_buffer: wgpu::Buffer,
+    result_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl FieldProbe {
+    /// Points evaluated per dispatch, longer probes take several.
+    const MAX_POINTS: usize = 4096;
+
+    /// `simulation_bind_group_layouts` are those of the particle kernels,
+    /// the probe points are bound after them.
+    pub fn new(
+        device: &wgpu::Device,
+        simulation_shader: &wgpu::ShaderModule,
+        simulation_bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> Self {
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Probe Params"),
+            size: std::mem::size_of::<ProbeParams>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let points_size = (Self::MAX_POINTS * std::mem::size_of::<Vec4>()) as u64;
+        let point_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Probe Points"),
+            size: points_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let result_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Probe Results"),
+            size: points_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Probe Readback"),
+            size: points_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Probe Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Probe Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: point_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: result_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut bind_group_layouts = simulation_bind_group_layouts.to_vec();
+        bind_group_layouts.push(&bind_group_layout);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Probe Pipeline Layout"),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Probe Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: simulation_shader,
+            entry_point: "probe_field",
+        });
+
+        Self {
+            params_buffer,
+            point_buffer,
+            result_buffer,
+            readback_buffer,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Field and potential at `points`, waiting for the GPU.
+    /// `simulation_bind_groups` match the layouts given to
+    /// [`FieldProbe::new`].
+    pub fn sample(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        simulation_bind_groups: &[&wgpu::BindGroup],
+        points: &[Vec3],
+    ) -> Vec<Vec4> {
+        let mut results = Vec::with_capacity(points.len());
+        for chunk in points.chunks(Self::MAX_POINTS) {
+            let params = ProbeParams {
+                point_count: chunk.len() as _,
+                _padding: [0; 3],
+            };
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+            let chunk: Vec<Vec4> = chunk.iter().map(|point| point.extend(1.)).collect();
+            queue.write_buffer(&self.point_buffer, 0, bytemuck::cast_slice(&chunk));
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Probe Encoder"),
+            });
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Probe Field"),
+            });
+            cpass.set_pipeline(&self.pipeline);
+            for (index, bind_group) in simulation_bind_groups.iter().enumerate() {
+                cpass.set_bind_group(index as _, bind_group, &[]);
+            }
+            cpass.set_bind_group(simulation_bind_groups.len() as _, &self.bind_group, &[]);
+            cpass.dispatch(dispatch_size(chunk.len() as _), 1, 1);
+            drop(cpass);
+            let size = (chunk.len() * std::mem::size_of::<Vec4>()) as u64;
+            encoder.copy_buffer_to_buffer(&self.result_buffer, 0, &self.readback_buffer, 0, size);
+            queue.submit(Some(encoder.finish()));
+
+            let slice = self.readback_buffer.slice(..size);
+            let mapped = slice.map_async(wgpu::MapMode::Read);
+            device.poll(wgpu::Maintain::Wait);
+            match pollster::block_on(mapped) {
+                Ok(()) => {
+                    results.extend_from_slice(bytemuck::cast_slice(&slice.get_mapped_range()));
+                    self.readback_buffer.unmap();
+                }
+                Err(_) => results.resize(results.len() + chunk.len(), Vec4::splat(f32::NAN)),
+            }
+        }
+        results
+    }
+}
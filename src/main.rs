@@ -1,10 +1,20 @@
 #![feature(array_zip, array_from_fn)]
-use std::time::{Duration, Instant};
+use std::{
+    fs::File,
+    io::{LineWriter, Write},
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use camera::Camera;
-use gfx_ctx::Context;
+use gfx_ctx::{Context, Diagnostics, GaussSurface, ParticleSpecies, Probe, ProbeSample};
 use glam::Vec3;
+use rand::{rngs::StdRng, SeedableRng};
+use scene::{
+    ChargeDistribution, ChargeUnit, Dielectric, DielectricShape, Electrode, Emitter, EmitterShape,
+    MagneticSource, Scene, Units, VelocityDistribution,
+};
+use timestep::FixedTimestep;
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
     event::{
@@ -17,6 +27,17 @@ use winit::{
 
 mod camera;
 mod gfx_ctx;
+mod scene;
+mod timestep;
+
+fn write_probe_csv(path: &str, samples: &[ProbeSample]) -> Result<()> {
+    let mut file = LineWriter::new(File::create(path)?);
+    writeln!(file, "{}", ProbeSample::CSV_HEADER)?;
+    for sample in samples {
+        sample.write_csv(&mut file)?;
+    }
+    Ok(())
+}
 
 fn main() -> Result<()> {
     env_logger::init();
@@ -24,6 +45,23 @@ fn main() -> Result<()> {
     let window = WindowBuilder::new().build(&event_loop)?;
     let size = window.inner_size();
 
+    // `--seed <n>` reproduces a previous run, the seed is printed either way.
+    let seed = match std::env::args().skip_while(|arg| arg != "--seed").nth(1) {
+        Some(seed) => seed.parse().context("--seed expects an unsigned integer")?,
+        None => rand::random(),
+    };
+    println!("Seed: {seed}");
+    // The charges of the R key keep drawing from the stream the initial ones
+    // came from, so the whole run stays reproducible from `seed`.
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut scene = Scene {
+        seed,
+        ..Scene::random(&mut rng)
+    };
+    scene
+        .magnets
+        .extend(MagneticSource::bottle(Vec3::ZERO, Vec3::Y, 1.2, 0.5, 0.05));
+
     let mut context = pollster::block_on({
         let PhysicalSize { width, height } = size;
         let camera = Camera::new(
@@ -33,15 +71,57 @@ fn main() -> Result<()> {
             Vec3::new(0.0, 0.0, 0.0),
             width as f32 / height as f32,
         );
-        Context::new(&window, width, height, camera)
+        Context::new(&window, width, height, camera, &scene)
     });
 
+    // `--diagnostics <path>` writes the diagnostics of every step to a CSV
+    // file, G prints them once a second.
+    let mut diagnostics_csv = match std::env::args()
+        .skip_while(|arg| arg != "--diagnostics")
+        .nth(1)
+    {
+        Some(path) => {
+            let mut file = LineWriter::new(
+                File::create(&path).with_context(|| format!("Failed to create {path}"))?,
+            );
+            writeln!(file, "{}", Diagnostics::CSV_HEADER)?;
+            Some(file)
+        }
+        None => None,
+    };
+    let mut log_diagnostics = false;
+    let mut last_diagnostics_log = Instant::now();
+    context.record_diagnostics = diagnostics_csv.is_some();
+
     let mut mouse_dragged = false;
 
     let rotate_speed = 0.0025;
     let zoom_speed = 0.002;
 
     let mut last_update_inst = Instant::now();
+    // `--time-scale <x>` sets the simulated seconds per wall-clock second and
+    // `--max-substeps <n>` how many steps a slow frame may catch up on.
+    let time_scale = match std::env::args()
+        .skip_while(|arg| arg != "--time-scale")
+        .nth(1)
+    {
+        Some(scale) => scale.parse().context("--time-scale expects a number")?,
+        None => 6.0,
+    };
+    let max_substeps = match std::env::args()
+        .skip_while(|arg| arg != "--max-substeps")
+        .nth(1)
+    {
+        Some(substeps) => substeps
+            .parse()
+            .context("--max-substeps expects an unsigned integer")?,
+        None => 8,
+    };
+    let mut timestep = FixedTimestep::new(
+        Duration::from_secs_f64(1.0 / 120.0),
+        time_scale,
+        max_substeps,
+    );
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
@@ -72,6 +152,335 @@ fn main() -> Result<()> {
                         },
                     ..
                 } => *control_flow = ControlFlow::Exit,
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::I),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    context.integrator = context.integrator.next();
+                    log::info!("Integrator: {:?}", context.integrator);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    context.set_field_mode(context.field_mode().next());
+                    log::info!("Field mode: {:?}", context.field_mode());
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::B),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    let boundary = context.particle_boundaries()[0].next();
+                    context.set_particle_boundaries([boundary; 6]);
+                    log::info!("Particle boundaries: {:?}", boundary);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::N),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    context.set_interaction(context.interaction().next());
+                    log::info!("Interaction: {:?}", context.interaction());
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::P),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    context.set_field_solver(context.field_solver().next());
+                    log::info!("Field solver: {:?}", context.field_solver());
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::R),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    scene.charges = Scene::random(&mut rng).charges;
+                    context.set_scene(&scene);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::T),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    if scene.distributions.is_empty() {
+                        scene.distributions.extend([
+                            ChargeDistribution::Ring {
+                                center: Vec3::ZERO,
+                                normal: Vec3::Y,
+                                radius: 0.5,
+                                charge: 0.5,
+                            },
+                            ChargeDistribution::Line {
+                                start: Vec3::new(0., -0.6, 0.),
+                                end: Vec3::new(0., 0.6, 0.),
+                                charge: -0.5,
+                            },
+                        ]);
+                    } else {
+                        scene.distributions.clear();
+                    }
+                    context.set_distributions(&scene.distributions);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::U),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    scene.units = if scene.units == Units::default() {
+                        // Elementary charges a micrometre apart, displayed
+                        // like the dimensionless scene.
+                        let mut units = Units::si(1.0e-6, ChargeUnit::Elementary, 1.);
+                        units.display_scale = Units::default().display_scale / units.field_scale();
+                        units
+                    } else {
+                        Units::default()
+                    };
+                    context.set_scene(&scene);
+                    log::info!("Units: {:?}", context.units());
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::K),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    // Field along the x axis through the middle of the domain,
+                    // as the particles see it and from the CPU reference.
+                    let center = (scene.domain.min + scene.domain.max) * 0.5;
+                    let start = Vec3::new(scene.domain.min.x, center.y, center.z);
+                    let end = Vec3::new(scene.domain.max.x, center.y, center.z);
+                    let probe = Probe::polyline(&[start, end], 512);
+                    for (path, samples) in [
+                        ("probe.csv", context.probe(&probe)),
+                        ("probe_reference.csv", context.probe_reference(&probe)),
+                    ] {
+                        match write_probe_csv(path, &samples) {
+                            Ok(()) => println!("Probe written to {path}"),
+                            Err(e) => eprintln!("{:?}", e),
+                        }
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::X),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    // Gauss's law around the first charge and through the
+                    // middle half of the domain.
+                    let center = (scene.domain.min + scene.domain.max) * 0.5;
+                    let quarter = (scene.domain.max - scene.domain.min) * 0.25;
+                    let surfaces = scene
+                        .charges
+                        .first()
+                        .map(|charge| GaussSurface::Sphere {
+                            center: charge.pos,
+                            radius: 0.1,
+                        })
+                        .into_iter()
+                        .chain([GaussSurface::Box {
+                            min: center - quarter,
+                            max: center + quarter,
+                        }]);
+                    for surface in surfaces {
+                        let flux = context.gauss_flux(&surface, 64);
+                        println!("Gauss flux through {:?}:", surface);
+                        println!(
+                            "  enclosed {:.4e} C, expected {:.4e} V m, analytic {:.4e} V m ({:+.2e}), sampled {:.4e} V m ({:+.2e}), discretisation error {:+.2e}",
+                            flux.enclosed_charge,
+                            flux.expected,
+                            flux.analytic,
+                            flux.analytic_error(),
+                            flux.sampled,
+                            flux.sampled_error(),
+                            flux.discretisation_error(),
+                        );
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::G),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    log_diagnostics = !log_diagnostics;
+                    context.record_diagnostics = log_diagnostics || diagnostics_csv.is_some();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::E),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    if scene.electrodes.is_empty() {
+                        scene.electrodes.extend(Electrode::capacitor(
+                            Vec3::ZERO,
+                            Vec3::X,
+                            1.2,
+                            0.6,
+                            1.,
+                        ));
+                    } else {
+                        scene.electrodes.clear();
+                    }
+                    context.set_electrodes(&scene.electrodes);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::D),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    if scene.dielectrics.is_empty() {
+                        scene.dielectrics.push(Dielectric {
+                            shape: DielectricShape::Sphere {
+                                center: Vec3::ZERO,
+                                radius: 0.3,
+                            },
+                            permittivity: 4.,
+                        });
+                    } else {
+                        scene.dielectrics.clear();
+                    }
+                    context.set_dielectrics(&scene.dielectrics);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::C),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    context.set_particle_coloring(context.particle_coloring().next());
+                    log::info!("Coloring: {:?}", context.particle_coloring());
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::V),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    if context.equipotentials().is_empty() {
+                        // Levels of the dimensionless scene, in volts.
+                        let scale = context.units().potential_scale();
+                        let levels = [-2., -1., -0.5, 0.5, 1., 2.].map(|level| level * scale);
+                        context.set_equipotentials(&levels);
+                    } else {
+                        context.set_equipotentials(&[]);
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::L),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    context.set_seeding(context.seeding().next());
+                    log::info!("Seeding: {:?}", context.seeding());
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::M),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    if scene.emitters.is_empty() {
+                        scene.emitters.push(Emitter {
+                            shape: EmitterShape::Disk {
+                                center: Vec3::new(-0.95, 0., 0.),
+                                normal: Vec3::X,
+                                radius: 0.1,
+                            },
+                            velocity: VelocityDistribution::Cone {
+                                direction: Vec3::X,
+                                spread: 0.05,
+                            },
+                            speed: 0.2..0.3,
+                            lifetime: 5.0..10.0,
+                            rate: 2.0e5,
+                        });
+                    } else {
+                        scene.emitters.clear();
+                    }
+                    context.set_emitters(&scene.emitters);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Q),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    let species = context.species();
+                    context.set_species(ParticleSpecies {
+                        charge: -species.charge,
+                        ..species
+                    });
+                }
                 WindowEvent::Resized(new_size) => {
                     context.resize(new_size.width, new_size.height);
                 }
@@ -113,7 +522,28 @@ fn main() -> Result<()> {
             Event::RedrawRequested(_) => {
                 context.camera.add_yaw(-0.001);
                 context.update();
-                context.simulate(0.);
+                for _ in 0..timestep.advance() {
+                    context.simulate(timestep.dt());
+                }
+                for diagnostics in context.poll_diagnostics() {
+                    if let Some(file) = &mut diagnostics_csv {
+                        if let Err(e) = diagnostics.write_csv(file) {
+                            eprintln!("{:?}", e);
+                        }
+                    }
+                    if log_diagnostics && last_diagnostics_log.elapsed() >= Duration::from_secs(1) {
+                        last_diagnostics_log = Instant::now();
+                        println!(
+                            "Step {}: {} alive, E = {:.4e} J (kinetic {:.4e}, potential {:.4e}), p = {:.4e} kg m/s",
+                            diagnostics.step,
+                            diagnostics.alive,
+                            diagnostics.total_energy(),
+                            diagnostics.kinetic_energy,
+                            diagnostics.potential_energy,
+                            diagnostics.momentum.length(),
+                        );
+                    }
+                }
                 match context.render() {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Lost) => {
@@ -5,6 +5,15 @@ struct Camera {
 };
 [[group(0), binding(0)]] var<uniform> camera : Camera;
 
+[[block]]
+struct Coloring {
+  mode: u32;
+  potential_scale: f32;
+};
+[[group(1), binding(0)]] var<uniform> coloring : Coloring;
+
+let COLORING_POTENTIAL: u32 = 1u;
+
 struct VertexInput {
   [[location(0)]] pos: vec4<f32>;
   [[location(1)]] vel: vec4<f32>;
@@ -16,14 +25,19 @@ struct VertexOutput {
   [[location(0)]] world_position: vec3<f32>;
   [[location(1)]] vel: vec3<f32>;
   [[location(2)]] life: f32;
+  [[location(3)]] potential: f32;
 };
 
 [[stage(vertex)]]
 fn vs_main(in: VertexInput) -> VertexOutput {
   let pos = in.pos.xyz;
   let vel = in.vel.xyz;
-  let clip_pos = camera.view_proj * vec4<f32>(pos, 1.0);
-  return VertexOutput(clip_pos, pos, vel, in.life);
+  var clip_pos = camera.view_proj * vec4<f32>(pos, 1.0);
+  // Absorbed particles are moved out of the clip volume.
+  if (in.life < 0.) {
+    clip_pos = vec4<f32>(2., 2., 2., 1.);
+  }
+  return VertexOutput(clip_pos, pos, vel, in.life, in.vel.w);
 }
 
 [[stage(fragment)]]
@@ -39,5 +53,11 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
   if (all(smoothStep(vec3<f32>(.2), vec3<f32>(.02), in.vel) <= vec3<f32>(0.5))) {
     a = a * 0.01;
   }
+  if (coloring.mode == COLORING_POTENTIAL) {
+    // Diverging map, blue for negative and red for positive potential.
+    let t = tanh(in.potential * coloring.potential_scale);
+    let hot = mix(vec3<f32>(.8), vec3<f32>(.8, .1, .1), max(t, 0.));
+    return vec4<f32>(mix(hot, vec3<f32>(.1, .2, .8), max(-t, 0.)), a);
+  }
   return vec4<f32>(normalize(col) , a);
 }
@@ -0,0 +1,827 @@
+use std::ops::Range;
+
+use glam::{Vec2, Vec3};
+use rand::Rng;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Charge {
+    pub q: f32,
+    pub pos: Vec3,
+}
+
+impl Charge {
+    pub fn new_rand(rng: &mut impl Rng) -> Self {
+        let q_range = 0.3;
+        Self {
+            q: rng.gen_range(-q_range..q_range),
+            pos: Vec3::from([0., 0., 0.].map(|_| rng.gen_range(-0.5..0.5))),
+        }
+    }
+}
+
+/// How the dimensionless scene maps to physical quantities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChargeUnit {
+    Coulomb,
+    /// Elementary charge.
+    Elementary,
+}
+
+impl ChargeUnit {
+    pub const ELEMENTARY_CHARGE: f32 = 1.602_176_6e-19;
+
+    pub fn coulombs(self) -> f32 {
+        match self {
+            Self::Coulomb => 1.,
+            Self::Elementary => Self::ELEMENTARY_CHARGE,
+        }
+    }
+}
+
+/// Physical units of a scene. Sources are evaluated as `q / r^2` with
+/// positions in world units and charges in [`Units::charge_unit`], then
+/// scaled into volts per metre and volts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Units {
+    /// Metres per world unit.
+    pub length_scale: f32,
+    /// Unit of the scene charges and of the particle species charge.
+    pub charge_unit: ChargeUnit,
+    /// `k_e` in N m^2 / C^2.
+    pub coulomb_constant: f32,
+    /// Rendering only. Field line tracers move by this many world units per
+    /// second per V/m.
+    pub display_scale: f32,
+}
+
+impl Units {
+    pub const COULOMB_CONSTANT: f32 = 8.987_552e9;
+
+    /// SI units with the world cube `2 * length_scale` metres wide.
+    pub fn si(length_scale: f32, charge_unit: ChargeUnit, display_scale: f32) -> Self {
+        Self {
+            length_scale,
+            charge_unit,
+            coulomb_constant: Self::COULOMB_CONSTANT,
+            display_scale,
+        }
+    }
+
+    /// V/m per unit of the dimensionless `q / r^2`.
+    pub fn field_scale(&self) -> f32 {
+        self.coulomb_constant * self.charge_unit.coulombs()
+            / (self.length_scale * self.length_scale)
+    }
+
+    /// Volts per unit of the dimensionless `q / r`.
+    pub fn potential_scale(&self) -> f32 {
+        self.coulomb_constant * self.charge_unit.coulombs() / self.length_scale
+    }
+}
+
+impl Default for Units {
+    /// Gaussian units with `k_e = 1` and unit lengths and charges, slowed
+    /// down for display.
+    fn default() -> Self {
+        Self {
+            length_scale: 1.,
+            charge_unit: ChargeUnit::Coulomb,
+            coulomb_constant: 1.,
+            display_scale: 0.02,
+        }
+    }
+}
+
+/// Smallest `r^2` the point charges are evaluated at, so that a point right
+/// on a charge gets a finite potential and no field instead of NaN.
+const CHARGE_SOFTENING: f32 = 1e-6;
+
+/// Field of a point charge in scene units, see [`Units::field_scale`].
+pub fn get_charge(pos: Vec3, charge: Charge) -> Vec3 {
+    let pc = pos - charge.pos;
+    let r2 = pc.dot(pc).max(CHARGE_SOFTENING);
+    pc * (charge.q / r2.powf(1.5))
+}
+
+/// CPU reference of the field the GPU bakes from the same sources, in scene
+/// units.
+pub fn get_field(p: Vec3, charges: &[Charge], distributions: &[ChargeDistribution]) -> Vec3 {
+    let field = charges
+        .iter()
+        .fold(Vec3::ZERO, |acc, &q| acc + get_charge(p, q));
+    distributions
+        .iter()
+        .fold(field, |acc, &d| acc + get_distribution(p, d))
+}
+
+/// Potential `get_charge` is the negative gradient of.
+pub fn get_charge_potential(pos: Vec3, charge: Charge) -> f32 {
+    let r2 = pos.distance_squared(charge.pos).max(CHARGE_SOFTENING);
+    charge.q / r2.sqrt()
+}
+
+/// CPU reference of the potential baked next to the field.
+pub fn get_potential(p: Vec3, charges: &[Charge], distributions: &[ChargeDistribution]) -> f32 {
+    let potential = charges
+        .iter()
+        .fold(0., |acc, &q| acc + get_charge_potential(p, q));
+    distributions
+        .iter()
+        .fold(potential, |acc, &d| acc + get_distribution_potential(p, d))
+}
+
+/// Extended and multipole sources with closed-form fields, in the same units
+/// as [`Charge`]. `charge` is the total charge of the bounded shapes.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum ChargeDistribution {
+    /// Uniformly charged segment from `start` to `end`.
+    Line { start: Vec3, end: Vec3, charge: f32 },
+    /// Uniformly charged thin ring orthogonal to `normal`.
+    Ring {
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+        charge: f32,
+    },
+    /// Uniformly charged disc orthogonal to `normal`, summed over concentric
+    /// rings.
+    Disc {
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+        charge: f32,
+    },
+    /// Infinite plane through `point` with surface charge `density`.
+    Plane {
+        point: Vec3,
+        normal: Vec3,
+        density: f32,
+    },
+    /// Ideal dipole with dipole moment `moment`.
+    Dipole { pos: Vec3, moment: Vec3 },
+    /// Ideal quadrupole symmetric around `axis`, `moment` is the `Q_zz`
+    /// component of its traceless quadrupole tensor.
+    Quadrupole { pos: Vec3, axis: Vec3, moment: f32 },
+}
+
+impl ChargeDistribution {
+    const SOFTENING: f32 = 1.0e-4;
+    pub const DISC_RINGS: usize = 32;
+    /// Point charges a bounded shape is split into for the grid deposit.
+    const SAMPLES: usize = 64;
+    /// Separation of the point charges standing in for the ideal multipoles
+    /// in the grid deposit.
+    const MULTIPOLE_SEPARATION: f32 = 1.0e-2;
+
+    /// Point charges approximating the distribution, for the grid deposit
+    /// of the Poisson solver. Infinite planes are cut to a square covering
+    /// `domain`.
+    pub fn point_charges(&self, domain: &Domain) -> Vec<Charge> {
+        let circle = |center: Vec3, normal: Vec3, radius: f32, q: f32| {
+            let normal = normal.normalize();
+            let u = normal.any_orthonormal_vector();
+            let v = normal.cross(u);
+            (0..Self::SAMPLES).map(move |i| {
+                let angle = i as f32 / Self::SAMPLES as f32 * std::f32::consts::TAU;
+                Charge {
+                    q: q / Self::SAMPLES as f32,
+                    pos: center + (u * angle.cos() + v * angle.sin()) * radius,
+                }
+            })
+        };
+        match *self {
+            Self::Line { start, end, charge } => (0..Self::SAMPLES)
+                .map(|i| Charge {
+                    q: charge / Self::SAMPLES as f32,
+                    pos: start.lerp(end, (i as f32 + 0.5) / Self::SAMPLES as f32),
+                })
+                .collect(),
+            Self::Ring {
+                center,
+                normal,
+                radius,
+                charge,
+            } => circle(center, normal, radius, charge).collect(),
+            Self::Disc {
+                center,
+                normal,
+                radius,
+                charge,
+            } => (0..Self::DISC_RINGS)
+                .flat_map(|i| {
+                    let r = radius * ((i as f32 + 0.5) / Self::DISC_RINGS as f32).sqrt();
+                    circle(center, normal, r, charge / Self::DISC_RINGS as f32)
+                })
+                .collect(),
+            Self::Plane {
+                point,
+                normal,
+                density,
+            } => {
+                let normal = normal.normalize();
+                let u = normal.any_orthonormal_vector();
+                let v = normal.cross(u);
+                let half_size = (domain.max - point)
+                    .abs()
+                    .max((domain.min - point).abs())
+                    .length();
+                let samples = Self::SAMPLES * 2;
+                let step = 2. * half_size / samples as f32;
+                let q = density * step * step;
+                (0..samples * samples)
+                    .map(|i| {
+                        let [x, y] =
+                            [i % samples, i / samples].map(|j| (j as f32 + 0.5) * step - half_size);
+                        Charge {
+                            q,
+                            pos: point + u * x + v * y,
+                        }
+                    })
+                    .collect()
+            }
+            Self::Dipole { pos, moment } => {
+                let offset = moment.normalize_or_zero() * Self::MULTIPOLE_SEPARATION * 0.5;
+                let q = moment.length() / Self::MULTIPOLE_SEPARATION;
+                vec![
+                    Charge {
+                        q,
+                        pos: pos + offset,
+                    },
+                    Charge {
+                        q: -q,
+                        pos: pos - offset,
+                    },
+                ]
+            }
+            Self::Quadrupole { pos, axis, moment } => {
+                let offset = axis.normalize() * Self::MULTIPOLE_SEPARATION;
+                let q = moment / (4. * Self::MULTIPOLE_SEPARATION * Self::MULTIPOLE_SEPARATION);
+                vec![
+                    Charge {
+                        q,
+                        pos: pos + offset,
+                    },
+                    Charge { q: -2. * q, pos },
+                    Charge {
+                        q,
+                        pos: pos - offset,
+                    },
+                ]
+            }
+        }
+    }
+}
+
+/// Complete elliptic integrals of the first and second kind `(K(m), E(m))`
+/// by the arithmetic-geometric mean.
+fn elliptic_integrals(m: f32) -> (f32, f32) {
+    let (mut a, mut b) = (1., (1. - m).sqrt());
+    let (mut sum, mut weight) = (0.5 * m, 0.5);
+    for _ in 0..8 {
+        let c = 0.5 * (a - b);
+        weight *= 2.;
+        sum += weight * c * c;
+        let mean = 0.5 * (a + b);
+        b = (a * b).sqrt();
+        a = mean;
+    }
+    let k = std::f32::consts::FRAC_PI_2 / a;
+    (k, k * (1. - sum))
+}
+
+/// Field and potential of a thin ring.
+fn get_ring(p: Vec3, center: Vec3, normal: Vec3, radius: f32, q: f32) -> (Vec3, f32) {
+    let normal = normal.normalize();
+    let d = p - center;
+    let z = d.dot(normal);
+    let perp = d - normal * z;
+    let rho = perp.length().max(1.0e-6);
+    let z2 = z * z + ChargeDistribution::SOFTENING;
+    let plus = (radius + rho).powi(2) + z2;
+    let minus = (radius - rho).powi(2) + z2;
+    let (k, e) = elliptic_integrals(4. * radius * rho / plus);
+    let scale = q / (std::f32::consts::PI * plus.sqrt());
+    let radial = scale / rho * (k - (radius * radius - rho * rho + z2) / minus * e);
+    let axial = 2. * scale * z * e / minus;
+    (perp / rho * radial + normal * axial, 2. * scale * k)
+}
+
+fn get_distribution_and_potential(p: Vec3, distribution: ChargeDistribution) -> (Vec3, f32) {
+    let softening = ChargeDistribution::SOFTENING;
+    match distribution {
+        ChargeDistribution::Line { start, end, charge } => {
+            let length = start.distance(end);
+            let dir = (end - start) / length;
+            let d = p - start;
+            let x = d.dot(dir);
+            let perp = d - dir * x;
+            let rho2 = perp.length_squared() + softening;
+            let r1 = (rho2 + x * x).sqrt();
+            let r2 = (rho2 + (length - x).powi(2)).sqrt();
+            let lambda = charge / length;
+            (
+                (perp / rho2 * ((length - x) / r2 + x / r1) + dir * (1. / r2 - 1. / r1)) * lambda,
+                lambda * ((r1 + r2 + length) / (r1 + r2 - length)).ln(),
+            )
+        }
+        ChargeDistribution::Ring {
+            center,
+            normal,
+            radius,
+            charge,
+        } => get_ring(p, center, normal, radius, charge),
+        ChargeDistribution::Disc {
+            center,
+            normal,
+            radius,
+            charge,
+        } => (0..ChargeDistribution::DISC_RINGS).fold((Vec3::ZERO, 0.), |(field, potential), i| {
+            let rings = ChargeDistribution::DISC_RINGS as f32;
+            let r = radius * ((i as f32 + 0.5) / rings).sqrt();
+            let (f, phi) = get_ring(p, center, normal, r, charge / rings);
+            (field + f, potential + phi)
+        }),
+        ChargeDistribution::Plane {
+            point,
+            normal,
+            density,
+        } => {
+            let normal = normal.normalize();
+            let d = (p - point).dot(normal);
+            let sign = if d == 0. { 0. } else { d.signum() };
+            let scale = std::f32::consts::TAU * density;
+            (normal * scale * sign, -scale * d.abs())
+        }
+        ChargeDistribution::Dipole { pos, moment } => {
+            let r = p - pos;
+            let r2 = r.length_squared() + softening;
+            let r3 = r2.powf(1.5);
+            let mr = moment.dot(r);
+            ((3. * mr / r2 * r - moment) / r3, mr / r3)
+        }
+        ChargeDistribution::Quadrupole { pos, axis, moment } => {
+            let axis = axis.normalize();
+            let r = p - pos;
+            let r2 = r.length_squared() + softening;
+            let r5 = r2.powf(2.5);
+            let a = axis.dot(r);
+            (
+                ((5. * a * a / r2 - 1.) * r - 2. * a * axis) * (0.75 * moment / r5),
+                moment * (3. * a * a - r2) / (4. * r5),
+            )
+        }
+    }
+}
+
+pub fn get_distribution(p: Vec3, distribution: ChargeDistribution) -> Vec3 {
+    get_distribution_and_potential(p, distribution).0
+}
+
+/// Potential `get_distribution` is the negative gradient of.
+pub fn get_distribution_potential(p: Vec3, distribution: ChargeDistribution) -> f32 {
+    get_distribution_and_potential(p, distribution).1
+}
+
+/// Magnetostatic sources, evaluated with Biot–Savart in units where
+/// `mu_0 / 4pi = 1`.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum MagneticSource {
+    /// Straight wire segment from `start` to `end` carrying `current`.
+    Wire {
+        start: Vec3,
+        end: Vec3,
+        current: f32,
+    },
+    /// Circular loop around `normal`, current flowing counter-clockwise when
+    /// looking against the normal.
+    Loop {
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+        current: f32,
+    },
+    /// Point dipole with magnetic moment `moment`.
+    Dipole { pos: Vec3, moment: Vec3 },
+}
+
+impl MagneticSource {
+    /// Number of straight segments a current loop is split into.
+    const LOOP_SEGMENTS: usize = 64;
+    const SOFTENING: f32 = 1.0e-4;
+
+    /// Two coaxial loops with parallel currents, strong field near the coils
+    /// and a weaker one between them.
+    pub fn bottle(
+        center: Vec3,
+        axis: Vec3,
+        separation: f32,
+        radius: f32,
+        current: f32,
+    ) -> [Self; 2] {
+        let axis = axis.normalize();
+        [-0.5, 0.5].map(|side| Self::Loop {
+            center: center + axis * separation * side,
+            normal: axis,
+            radius,
+            current,
+        })
+    }
+}
+
+fn get_wire(p: Vec3, start: Vec3, end: Vec3, current: f32) -> Vec3 {
+    let dir = (end - start).normalize_or_zero();
+    let (ap, bp) = (p - start, p - end);
+    let perp = ap - dir * ap.dot(dir);
+    let d2 = perp.length_squared() + MagneticSource::SOFTENING;
+    let (cos_a, cos_b) = (
+        ap.normalize_or_zero().dot(dir),
+        bp.normalize_or_zero().dot(dir),
+    );
+    dir.cross(perp) * (current * (cos_a - cos_b) / d2)
+}
+
+pub fn get_magnet(p: Vec3, source: MagneticSource) -> Vec3 {
+    match source {
+        MagneticSource::Wire {
+            start,
+            end,
+            current,
+        } => get_wire(p, start, end, current),
+        MagneticSource::Loop {
+            center,
+            normal,
+            radius,
+            current,
+        } => {
+            let normal = normal.normalize();
+            let u = normal.any_orthonormal_vector();
+            let v = normal.cross(u);
+            let point = |i: usize| {
+                let angle = i as f32 / MagneticSource::LOOP_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            (0..MagneticSource::LOOP_SEGMENTS).fold(Vec3::ZERO, |acc, i| {
+                acc + get_wire(p, point(i), point(i + 1), current)
+            })
+        }
+        MagneticSource::Dipole { pos, moment } => {
+            let r = p - pos;
+            let r2 = r.length_squared() + MagneticSource::SOFTENING;
+            let r_hat = r / r2.sqrt();
+            (3. * moment.dot(r_hat) * r_hat - moment) / r2.powf(1.5)
+        }
+    }
+}
+
+pub fn get_magnetic_field(p: Vec3, sources: &[MagneticSource]) -> Vec3 {
+    sources
+        .iter()
+        .fold(Vec3::ZERO, |acc, &source| acc + get_magnet(p, source))
+}
+
+/// Conductor shapes, described by their signed distance.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum ElectrodeShape {
+    /// Rectangular plate of `half_size` in its plane and `thickness` along
+    /// `normal`.
+    Plate {
+        center: Vec3,
+        normal: Vec3,
+        half_size: Vec2,
+        thickness: f32,
+    },
+    /// Thin-walled tube of `length` along `axis`.
+    Cylinder {
+        center: Vec3,
+        axis: Vec3,
+        radius: f32,
+        length: f32,
+    },
+    /// Square mesh of crossed wires spaced `pitch` apart in the plane
+    /// orthogonal to `normal`.
+    Grid {
+        center: Vec3,
+        normal: Vec3,
+        half_size: f32,
+        pitch: f32,
+        wire_radius: f32,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+}
+
+impl ElectrodeShape {
+    /// Signed distance to the surface, negative inside the conductor.
+    pub fn distance(&self, p: Vec3) -> f32 {
+        match *self {
+            Self::Plate {
+                center,
+                normal,
+                half_size,
+                thickness,
+            } => {
+                let normal = normal.normalize();
+                let u = normal.any_orthonormal_vector();
+                let v = normal.cross(u);
+                let d = p - center;
+                let q = Vec3::new(d.dot(u), d.dot(v), d.dot(normal)).abs()
+                    - half_size.extend(thickness * 0.5);
+                q.max(Vec3::ZERO).length() + q.max_element().min(0.)
+            }
+            Self::Cylinder {
+                center,
+                axis,
+                radius,
+                length,
+            } => {
+                let axis = axis.normalize();
+                let d = p - center;
+                let along = d.dot(axis);
+                let radial = (d - axis * along).length();
+                let q = Vec2::new((radial - radius).abs(), along.abs() - length * 0.5);
+                q.max(Vec2::ZERO).length() + q.max_element().min(0.)
+            }
+            Self::Grid {
+                center,
+                normal,
+                half_size,
+                pitch,
+                wire_radius,
+            } => {
+                let normal = normal.normalize();
+                let u = normal.any_orthonormal_vector();
+                let v = normal.cross(u);
+                let d = p - center;
+                let [x, y, z] = [d.dot(u), d.dot(v), d.dot(normal)];
+                let wire = |a: f32| Vec2::new(a - (a / pitch).round() * pitch, z).length();
+                let wires = wire(x).min(wire(y)) - wire_radius;
+                let outside = x.abs().max(y.abs()) - half_size;
+                wires.max(outside)
+            }
+            Self::Sphere { center, radius } => (p - center).length() - radius,
+        }
+    }
+}
+
+/// Conductor held at a fixed potential.
+#[derive(Clone, Copy, Debug)]
+pub struct Electrode {
+    pub shape: ElectrodeShape,
+    /// In volts.
+    pub potential: f32,
+}
+
+impl Electrode {
+    /// Two parallel plates `separation` apart held at `-voltage / 2` and
+    /// `voltage / 2`, the positive one on the side `normal` points to.
+    pub fn capacitor(
+        center: Vec3,
+        normal: Vec3,
+        separation: f32,
+        half_size: f32,
+        voltage: f32,
+    ) -> [Self; 2] {
+        let normal = normal.normalize();
+        [-0.5, 0.5].map(|side| Self {
+            shape: ElectrodeShape::Plate {
+                center: center + normal * separation * side,
+                normal,
+                half_size: Vec2::splat(half_size),
+                thickness: 0.02,
+            },
+            potential: voltage * side,
+        })
+    }
+}
+
+/// Regions of a linear dielectric.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum DielectricShape {
+    Block { center: Vec3, half_size: Vec3 },
+    Sphere { center: Vec3, radius: f32 },
+}
+
+impl DielectricShape {
+    pub fn contains(&self, p: Vec3) -> bool {
+        match *self {
+            Self::Block { center, half_size } => (p - center).abs().cmple(half_size).all(),
+            Self::Sphere { center, radius } => p.distance_squared(center) <= radius * radius,
+        }
+    }
+}
+
+/// Dielectric with relative permittivity `permittivity`, vacuum is `1`.
+#[derive(Clone, Copy, Debug)]
+pub struct Dielectric {
+    pub shape: DielectricShape,
+    pub permittivity: f32,
+}
+
+/// Where an emitter places new particles.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum EmitterShape {
+    Point {
+        pos: Vec3,
+    },
+    /// Filled disk orthogonal to `normal`.
+    Disk {
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+    },
+    /// Surface of a sphere.
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    /// Rectangle orthogonal to `normal`.
+    Plane {
+        center: Vec3,
+        normal: Vec3,
+        half_size: Vec2,
+    },
+    /// Volume of an axis-aligned box.
+    Box {
+        center: Vec3,
+        half_size: Vec3,
+    },
+    /// Sphere surface of `radius` around a randomly picked scene charge.
+    Charges {
+        radius: f32,
+    },
+}
+
+/// Direction of the initial velocity of emitted particles.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum VelocityDistribution {
+    /// Uniformly random direction.
+    Isotropic,
+    /// Uniform in the cone of half-angle `spread` radians around `direction`.
+    Cone { direction: Vec3, spread: f32 },
+    /// Along the emitter normal, radially outwards for spheres, points and
+    /// charges, isotropic for boxes.
+    Normal,
+}
+
+/// Particle source replacing the uniform respawn in the domain.
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    pub shape: EmitterShape,
+    pub velocity: VelocityDistribution,
+    /// Speed range, sampled uniformly.
+    pub speed: Range<f32>,
+    pub lifetime: Range<f32>,
+    /// Particles emitted per unit of simulated time.
+    pub rate: f32,
+}
+
+/// Boundary condition on one face of the field solver grid.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Boundary {
+    /// Fixed potential in volts.
+    Dirichlet(f32),
+    /// Vanishing normal derivative of the potential.
+    Neumann,
+}
+
+/// Boundary conditions of the `-x, +x, -y, +y, -z, +z` faces.
+pub type Boundaries = [Boundary; 6];
+
+/// Axis-aligned simulation box, particles live inside it and the field grids
+/// cover it with `resolution` nodes per axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Domain {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub resolution: [u32; 3],
+}
+
+impl Domain {
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// Distance between grid nodes, node `i` sits at `min + i * spacing`.
+    pub fn spacing(&self) -> Vec3 {
+        self.size() / Vec3::from(self.resolution.map(|len| len as f32))
+    }
+
+    pub fn node_position(&self, node: [u32; 3]) -> Vec3 {
+        self.min + Vec3::from(node.map(|i| i as f32)) * self.spacing()
+    }
+
+    /// Grid coordinates of `p`, integer at the nodes.
+    pub fn grid_coords(&self, p: Vec3) -> Vec3 {
+        (p - self.min) / self.spacing()
+    }
+
+    pub fn node_count(&self) -> u32 {
+        self.resolution.iter().product()
+    }
+}
+
+impl Default for Domain {
+    fn default() -> Self {
+        Self {
+            min: Vec3::splat(-1.),
+            max: Vec3::ONE,
+            resolution: [64; 3],
+        }
+    }
+}
+
+/// Field sources and particle emitters the simulation is set up from.
+#[derive(Clone, Debug)]
+pub struct Scene {
+    pub charges: Vec<Charge>,
+    pub distributions: Vec<ChargeDistribution>,
+    pub magnets: Vec<MagneticSource>,
+    /// Only shape the field of the Poisson solver, particles are absorbed by
+    /// them in every mode.
+    pub electrodes: Vec<Electrode>,
+    /// Polarize in the Poisson solve only, like the electrodes.
+    pub dielectrics: Vec<Dielectric>,
+    pub boundaries: Boundaries,
+    pub units: Units,
+    /// Only read when the context is created, the grids are sized from it.
+    pub domain: Domain,
+    /// Empty keeps the uniform respawn in the domain.
+    pub emitters: Vec<Emitter>,
+    /// Master seed of the particle initialisation and respawns on the GPU.
+    pub seed: u64,
+}
+
+impl Scene {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        Self {
+            charges: std::iter::once(Charge {
+                q: -0.1,
+                pos: Vec3::ZERO,
+            })
+            .chain((0..6).map(|_| Charge::new_rand(rng)))
+            .collect(),
+            distributions: Vec::new(),
+            magnets: Vec::new(),
+            electrodes: Vec::new(),
+            dielectrics: Vec::new(),
+            boundaries: [Boundary::Dirichlet(0.); 6],
+            units: Units::default(),
+            domain: Domain::default(),
+            emitters: Vec::new(),
+            seed: rng.gen(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ring of radius `r` and charge `q` around the z axis, its on-axis field
+    /// and potential in closed form.
+    fn on_axis(z: f32, r: f32, q: f32) -> (f32, f32) {
+        let d2 = r * r + z * z;
+        (q * z / d2.powf(1.5), q / d2.sqrt())
+    }
+
+    #[test]
+    fn elliptic_integrals_at_zero() {
+        let (k, e) = elliptic_integrals(0.);
+        assert!((k - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!((e - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn elliptic_integrals_at_half() {
+        // K(1/2) and E(1/2) from tables.
+        let (k, e) = elliptic_integrals(0.5);
+        assert!((k - 1.854_075).abs() < 1e-5, "{}", k);
+        assert!((e - 1.350_644).abs() < 1e-5, "{}", e);
+    }
+
+    #[test]
+    fn ring_on_axis() {
+        let (r, q) = (0.8, 1.5);
+        let center = Vec3::new(0.1, 0.2, 0.3);
+        for z in [-2., -0.5, 0., 0.25, 1.] {
+            let (field, potential) = get_ring(center + Vec3::Z * z, center, Vec3::Z, r, q);
+            let (expected_field, expected_potential) = on_axis(z, r, q);
+            assert!((field.z - expected_field).abs() < 1e-3, "{} {}", z, field);
+            assert!(
+                (potential / expected_potential - 1.).abs() < 1e-3,
+                "{} {}",
+                z,
+                potential
+            );
+        }
+    }
+}
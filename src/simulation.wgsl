@@ -24,78 +24,520 @@ struct Particle {
   pos: vec4<f32>;
   vel: vec4<f32>;
   life: f32;
+  charge: f32;
+  mass: f32;
 };
 
-fn generate_particle(seed: u32) -> Particle {
-  var p : Particle;
-
-  p.pos = rand4(seed);
-  p.vel = rand4(seed + 1u) * 0.1;
-  p.life = 50. + (hash(seed) * 0.5 + 0.5) * 50.;
-  return p;
-}
-
 [[block]]
 struct ParticleData {
   data: [[stride(48)]] array<Particle>;
 };
 
+// `seed` starts from the master seed of the scene and advances every step.
 [[block]]
 struct Time {
   dt: f32;
   instant: f32;
+  seed: u32;
+};
+
+[[block]]
+struct Species {
+  charge: f32;
+  mass: f32;
 };
 
 [[group(0), binding(0)]]
 var<storage, read_write> particles: ParticleData;
 [[group(1), binding(0)]]
 var<uniform> time: Time;
+[[group(1), binding(1)]]
+var<uniform> species: Species;
 
-[[stage(compute), workgroup_size(256, 1, 1)]]
-fn integrate(
-    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
-) {
-  let id = global_id.x;
-  let p = &particles.data[id];
-  let curr_pos = (*p).pos;
-  let curr_vel = (*p).vel;
-  let curr_life = (*p).life;
+// Per-axis behaviour of the `-1` and `1` faces, see `BOUNDARY_*`.
+[[block]]
+struct ParticleBoundaries {
+  lower: vec3<u32>;
+  upper: vec3<u32>;
+};
 
-  // let new_pos = curr_pos + curr_vel * time.dt;
-  let new_pos = curr_pos + curr_vel * 0.1;
-  let new_life = curr_life - curr_vel.w;
+[[group(1), binding(2)]]
+var<uniform> particle_boundaries: ParticleBoundaries;
 
-  if (new_life < 0. || abs(new_pos.x) > 1.
-                    || abs(new_pos.y) > 1.
-		    || abs(new_pos.z) > 1.) {
-    (*p) = generate_particle(id);
-    return;
-  }
-  (*p).pos = new_pos;
-  (*p).life = new_life;
+let BOUNDARY_RESPAWN: u32 = 0u;
+let BOUNDARY_REFLECT: u32 = 1u;
+let BOUNDARY_PERIODIC: u32 = 2u;
+let BOUNDARY_ABSORB: u32 = 3u;
+
+// Negative lifetimes of the particles taken out of the simulation, each
+// brought back by its own event.
+// Waiting for the emitter budget.
+let LIFE_PARKED: f32 = -1.;
+// Left through an absorbing face.
+let LIFE_ABSORBED: f32 = -2.;
+// Hit an electrode.
+let LIFE_ELECTRODE: f32 = -3.;
+
+// Random stream of particle `id` in the current step.
+fn particle_seed(id: u32) -> u32 {
+  return ihash(id) ^ ihash(time.seed);
 }
 
-[[group(1), binding(0)]]
+[[group(2), binding(0)]]
 var field_texture: texture_3d<f32>;
-[[group(1), binding(1)]]
+[[group(2), binding(1)]]
 var field_sampler: sampler;
+[[group(2), binding(2)]]
+var magnetic_texture: texture_3d<f32>;
+[[group(2), binding(3)]]
+var<storage, read> charges: Charges;
 
-fn get_charge(pos: vec3<f32>) -> vec3<f32> {
-  // potentially mouse
-  let chargePosition = vec3<f32>(0.); // + .5;
-  let q = -0.2;
-  let pc = pos - chargePosition;
-  let r2 = dot(pc, pc);
-  let E = pc * q / (pow(r2, 1.5) + 1.0e-2);
-  return E;
+[[block]]
+struct FieldParams {
+  mode: u32;
+  charge_count: u32;
+  interaction: u32;
+  distribution_count: u32;
+  // See `Units` in `scene.rs`, `field_scale` takes the dimensionless
+  // `q / r^2` to V/m and `charge_scale` the particle charges to coulombs.
+  field_scale: f32;
+  potential_scale: f32;
+  display_scale: f32;
+  charge_scale: f32;
+  length_scale: f32;
+  // Refinement bricks around the charges, zero for the grid solvers.
+  brick_count: u32;
+  // Radius around the charges evaluated analytically on top of the texture
+  // in the hybrid field mode, zero otherwise.
+  near_cutoff: f32;
+  // Leading, strongest charges the near field is evaluated for.
+  near_count: u32;
+  // Bounds of the simulation domain the field textures span.
+  domain_min: vec3<f32>;
+  domain_max: vec3<f32>;
+};
+
+[[group(2), binding(4)]]
+var<uniform> field_params: FieldParams;
+
+// `p` relative to the domain, `0` at `domain_min` and `1` at `domain_max`.
+fn domain_coords(p: vec3<f32>) -> vec3<f32> {
+  return (p - field_params.domain_min) / (field_params.domain_max - field_params.domain_min);
 }
 
-fn get_field(p: vec3<f32>) -> vec3<f32> {
-  var res = textureSampleLevel(field_texture, field_sampler, p, 0.).xyz;
-  res = res * .02 + get_charge(p) * 0.01;
+// `pos.w` is the direction field lines are traced in, `-1` to follow them
+// backwards.
+fn generate_particle(seed: u32) -> Particle {
+  var p : Particle;
+
+  let t = rand3(seed) * 0.5 + 0.5;
+  p.pos = vec4<f32>(mix(field_params.domain_min, field_params.domain_max, t), 1.);
+  p.vel = rand4(seed + 1u) * 0.1;
+  p.life = 5. + (hash(seed) * 0.5 + 0.5) * 5.;
+  p.charge = species.charge;
+  p.mass = species.mass;
+  return p;
+}
+
+// `x` is 1 for field grid nodes inside a conductor.
+[[block]]
+struct Electrodes {
+  data: [[stride(8)]] array<vec2<f32>>;
+};
+
+[[group(2), binding(5)]]
+var<storage, read> electrodes: Electrodes;
+
+fn inside_electrode(p: vec3<f32>) -> bool {
+  let size = textureDimensions(field_texture);
+  let c = vec3<i32>(round(domain_coords(p) * vec3<f32>(size)));
+  if (any(c < vec3<i32>(0)) || any(c >= size)) {
+    return false;
+  }
+  return electrodes.data[c.x + size.x * (c.y + size.y * c.z)].x > 0.5;
+}
+
+// Field of the other particles, evaluated once per step by the Barnes–Hut
+// pass.
+[[block]]
+struct Interaction {
+  data: [[stride(16)]] array<vec4<f32>>;
+};
+
+[[group(2), binding(6)]]
+var<storage, read> interaction: Interaction;
+
+[[group(2), binding(7)]]
+var<storage, read> distributions: Distributions;
+[[group(2), binding(8)]]
+var brick_atlas: texture_3d<f32>;
+[[group(2), binding(9)]]
+var<storage, read> bricks: Bricks;
+
+// Particle-particle field of the particle being integrated, held fixed over
+// the substeps of the integrators.
+var<private> interaction_field: vec3<f32>;
+
+fn load_interaction(id: u32) {
+  if (field_params.interaction != 0u) {
+    interaction_field = interaction.data[id].xyz;
+  }
+}
+
+// Texture coordinates of `p`, grid node `i` sits at the center of texel `i`.
+fn field_texcoords(p: vec3<f32>) -> vec3<f32> {
+  let size = vec3<f32>(textureDimensions(field_texture));
+  return domain_coords(p) + 0.5 / size;
+}
+
+// Baked field and potential at `p`, from the finest refinement brick
+// containing it and from the field texture elsewhere.
+fn sample_field(p: vec3<f32>) -> vec4<f32> {
+  let last = vec3<f32>(f32(BRICK_SIZE - 1u));
+  var finest = field_params.brick_count;
+  var spacing = 0.;
+  var coords = vec3<f32>(0.);
+  for (var i = 0u; i < field_params.brick_count; i = i + 1u) {
+    let brick = bricks.data[i];
+    let c = (p - brick.min) / brick.spacing;
+    let inside = all(c >= vec3<f32>(0.)) && all(c <= last);
+    if (inside && (finest == field_params.brick_count || brick.spacing < spacing)) {
+      finest = i;
+      spacing = brick.spacing;
+      coords = c;
+    }
+  }
+  if (finest < field_params.brick_count) {
+    let size = vec3<f32>(textureDimensions(brick_atlas));
+    let texel = coords + vec3<f32>(f32(finest * BRICK_SIZE), 0., 0.) + 0.5;
+    return textureSampleLevel(brick_atlas, field_sampler, texel / size, 0.);
+  }
+  return textureSampleLevel(field_texture, field_sampler, field_texcoords(p), 0.);
+}
+
+// Field and potential of the charges near `p` that the texture leaves out in
+// the hybrid field mode.
+fn get_near_field(p: vec3<f32>) -> vec4<f32> {
+  var res = vec4<f32>(0.);
+  for (var i = 0u; i < field_params.near_count; i = i + 1u) {
+    let charge = charges.data[i];
+    if (distance(p, charge.pos) < field_params.near_cutoff) {
+      let field = vec4<f32>(get_charge(p, charge), get_charge_potential(p, charge));
+      res = res + field * near_weight(p, charge, field_params.near_cutoff);
+    }
+  }
   return res;
 }
 
+let FIELD_MODE_TEXTURE: u32 = 0u;
+let FIELD_MODE_ANALYTIC: u32 = 1u;
+
+fn get_field(p: vec3<f32>) -> vec3<f32> {
+  var res = vec3<f32>(0.);
+  if (field_params.mode == FIELD_MODE_ANALYTIC) {
+    for (var i = 0u; i < field_params.charge_count; i = i + 1u) {
+      res = res + get_charge(p, charges.data[i]);
+    }
+    for (var i = 0u; i < field_params.distribution_count; i = i + 1u) {
+      res = res + get_distribution(p, distributions.data[i]).xyz;
+    }
+  } else {
+    res = sample_field(p).xyz;
+    if (field_params.near_cutoff > 0.) {
+      res = res + get_near_field(p).xyz;
+    }
+  }
+  return (res + interaction_field) * field_params.field_scale;
+}
+
+// Velocity of the field line tracers.
+fn get_tracer_velocity(p: vec3<f32>) -> vec3<f32> {
+  return get_field(p) * field_params.display_scale;
+}
+
+// Charge-to-mass ratio of a particle in C/kg over the length scale, so that
+// `qm * (E + L v x B)` is an acceleration in world units.
+fn world_charge_to_mass(id: u32) -> f32 {
+  let p = particles.data[id];
+  return p.charge * field_params.charge_scale / (p.mass * field_params.length_scale);
+}
+
+// Electrostatic potential in volts. The particle-particle interaction has no
+// potential here.
+fn get_potential(p: vec3<f32>) -> f32 {
+  var res = 0.;
+  if (field_params.mode == FIELD_MODE_ANALYTIC) {
+    for (var i = 0u; i < field_params.charge_count; i = i + 1u) {
+      res = res + get_charge_potential(p, charges.data[i]);
+    }
+    for (var i = 0u; i < field_params.distribution_count; i = i + 1u) {
+      res = res + get_distribution(p, distributions.data[i]).w;
+    }
+  } else {
+    res = sample_field(p).w;
+    if (field_params.near_cutoff > 0.) {
+      res = res + get_near_field(p).w;
+    }
+  }
+  return res * field_params.potential_scale;
+}
+
+fn get_magnetic_field(p: vec3<f32>) -> vec3<f32> {
+  return textureSampleLevel(magnetic_texture, field_sampler, field_texcoords(p), 0.).xyz;
+}
+
+struct Emitter {
+  center: vec3<f32>;
+  shape: u32;
+  normal: vec3<f32>;
+  radius: f32;
+  half_size: vec3<f32>;
+  cumulative_rate: f32;
+  direction: vec3<f32>;
+  velocity: u32;
+  speed: vec2<f32>;
+  lifetime: vec2<f32>;
+  spread: f32;
+};
+
+[[block]]
+struct Emitters {
+  data: [[stride(96)]] array<Emitter>;
+};
+
+// `budget` particles may be emitted this step, `spawned` counts the claims.
+[[block]]
+struct EmitParams {
+  emitter_count: u32;
+  budget: u32;
+  total_rate: f32;
+  seeding: u32;
+  seed_radius: f32;
+};
+
+[[block]]
+struct SpawnCounter {
+  spawned: atomic<u32>;
+};
+
+[[group(1), binding(3)]]
+var<storage, read> emitters: Emitters;
+[[group(1), binding(4)]]
+var<uniform> emit_params: EmitParams;
+[[group(1), binding(5)]]
+var<storage, read_write> spawn_counter: SpawnCounter;
+
+let EMITTER_POINT: u32 = 0u;
+let EMITTER_DISK: u32 = 1u;
+let EMITTER_SPHERE: u32 = 2u;
+let EMITTER_PLANE: u32 = 3u;
+let EMITTER_BOX: u32 = 4u;
+let EMITTER_CHARGES: u32 = 5u;
+
+let SEEDING_EMITTERS: u32 = 0u;
+let SEEDING_FLUX: u32 = 1u;
+
+let VELOCITY_ISOTROPIC: u32 = 0u;
+let VELOCITY_CONE: u32 = 1u;
+let VELOCITY_NORMAL: u32 = 2u;
+
+let TAU: f32 = 6.283185307179586;
+
+// Direction on the unit sphere from two uniform numbers in `[0, 1]`.
+fn unit_vector(u: vec2<f32>) -> vec3<f32> {
+  let z = u.x * 2. - 1.;
+  let phi = u.y * TAU;
+  let s = sqrt(max(0., 1. - z * z));
+  return vec3<f32>(s * cos(phi), s * sin(phi), z);
+}
+
+fn tangent(n: vec3<f32>) -> vec3<f32> {
+  if (abs(n.x) > 0.9) {
+    return normalize(cross(n, vec3<f32>(0., 1., 0.)));
+  }
+  return normalize(cross(n, vec3<f32>(1., 0., 0.)));
+}
+
+fn emit(seed: u32) -> Particle {
+  let u = rand4(seed) * 0.5 + 0.5;
+  let v = rand4(seed + 1u) * 0.5 + 0.5;
+
+  // Emitters are picked proportionally to their rate.
+  let pick = hash(seed + 2u) * 0.5 + 0.5;
+  var index = 0u;
+  loop {
+    if (index + 1u >= emit_params.emitter_count
+        || emitters.data[index].cumulative_rate > pick * emit_params.total_rate) {
+      break;
+    }
+    index = index + 1u;
+  }
+  let e = emitters.data[index];
+
+  let t = tangent(e.normal);
+  let b = cross(e.normal, t);
+  var pos = e.center;
+  var outward = unit_vector(u.zw);
+  if (e.shape == EMITTER_DISK) {
+    let angle = u.y * TAU;
+    pos = e.center + (t * cos(angle) + b * sin(angle)) * e.radius * sqrt(u.x);
+    outward = e.normal;
+  } elseif (e.shape == EMITTER_SPHERE) {
+    pos = e.center + outward * e.radius;
+  } elseif (e.shape == EMITTER_PLANE) {
+    pos = e.center + t * (u.x * 2. - 1.) * e.half_size.x
+                   + b * (u.y * 2. - 1.) * e.half_size.y;
+    outward = e.normal;
+  } elseif (e.shape == EMITTER_BOX) {
+    pos = e.center + (u.xyz * 2. - 1.) * e.half_size;
+  } elseif (e.shape == EMITTER_CHARGES && field_params.charge_count > 0u) {
+    let charge = min(u32(u.x * f32(field_params.charge_count)), field_params.charge_count - 1u);
+    pos = charges.data[charge].pos + outward * e.radius;
+  }
+
+  var dir = unit_vector(v.yz);
+  if (e.velocity == VELOCITY_CONE) {
+    let cos_theta = mix(1., cos(e.spread), v.y);
+    let sin_theta = sqrt(max(0., 1. - cos_theta * cos_theta));
+    let phi = v.z * TAU;
+    let dt = tangent(e.direction);
+    let db = cross(e.direction, dt);
+    dir = e.direction * cos_theta + (dt * cos(phi) + db * sin(phi)) * sin_theta;
+  } elseif (e.velocity == VELOCITY_NORMAL) {
+    dir = outward;
+  }
+
+  var p: Particle;
+  p.pos = vec4<f32>(pos, 1.);
+  p.vel = vec4<f32>(dir * mix(e.speed.x, e.speed.y, v.x), 0.);
+  p.life = mix(e.lifetime.x, e.lifetime.y, v.w);
+  p.charge = species.charge;
+  p.mass = species.mass;
+  return p;
+}
+
+let GOLDEN_ANGLE: f32 = 2.399963229728653;
+
+// Seeds field line `id` on a small sphere around a charge. Every charge gets
+// a share of the particles proportional to `|q|`, spread over its sphere as
+// a Fibonacci lattice, and lines of negative charges are traced backwards.
+fn seed_field_line(id: u32) -> Particle {
+  var p = generate_particle(particle_seed(id));
+  let count = field_params.charge_count;
+  var total = 0.;
+  for (var i = 0u; i < count; i = i + 1u) {
+    total = total + abs(charges.data[i].q);
+  }
+  if (total <= 0.) {
+    return p;
+  }
+
+  let particle_count = f32(arrayLength(&particles.data));
+  let pick = (f32(id) + 0.5) / particle_count * total;
+  var index = 0u;
+  var before = 0.;
+  loop {
+    let q = abs(charges.data[index].q);
+    if (index + 1u >= count || before + q > pick) {
+      break;
+    }
+    before = before + q;
+    index = index + 1u;
+  }
+  let charge = charges.data[index];
+  let share = abs(charge.q);
+
+  let t = clamp((pick - before) / share, 0., 1.);
+  let z = 1. - 2. * t;
+  let s = sqrt(max(0., 1. - z * z));
+  let phi = t * share / total * particle_count * GOLDEN_ANGLE;
+  let dir = vec3<f32>(s * cos(phi), s * sin(phi), z);
+  p.pos = vec4<f32>(charge.pos + dir * emit_params.seed_radius, sign(charge.q));
+  p.vel = vec4<f32>(0.);
+  return p;
+}
+
+// Replaces a dead particle. With emitters it is parked, `LIFE_PARKED`, once
+// the budget of the step is used up.
+fn respawn(id: u32) {
+  let p = &particles.data[id];
+  if (emit_params.seeding == SEEDING_FLUX) {
+    (*p) = seed_field_line(id);
+    return;
+  }
+  if (emit_params.emitter_count == 0u) {
+    (*p) = generate_particle(particle_seed(id));
+    return;
+  }
+  if (atomicAdd(&spawn_counter.spawned, 1u) < emit_params.budget) {
+    (*p) = emit(particle_seed(id));
+  } else {
+    (*p).life = LIFE_PARKED;
+  }
+}
+
+// Ages the particle and either stores the new state or respawns it once it
+// has expired. Particles hitting an electrode are taken out until the
+// electrodes change, those leaving the domain are handled per face by
+// `particle_boundaries`.
+fn advance(id: u32, new_pos: vec3<f32>, new_vel: vec3<f32>) {
+  let p = &particles.data[id];
+  // Parked and absorbed particles have a negative lifetime.
+  if ((*p).life < 0.) {
+    return;
+  }
+  let new_life = (*p).life - time.dt;
+  var pos = new_pos;
+  var vel = new_vel;
+
+  let lower = field_params.domain_min;
+  let upper = field_params.domain_max;
+  for (var axis = 0u; axis < 3u; axis = axis + 1u) {
+    // The face crossed and the shift to the opposite one.
+    var face = 0.;
+    var wrap = 0.;
+    var boundary = BOUNDARY_RESPAWN;
+    if (pos[axis] < lower[axis]) {
+      face = lower[axis];
+      wrap = upper[axis] - lower[axis];
+      boundary = particle_boundaries.lower[axis];
+    } elseif (pos[axis] > upper[axis]) {
+      face = upper[axis];
+      wrap = lower[axis] - upper[axis];
+      boundary = particle_boundaries.upper[axis];
+    } else {
+      continue;
+    }
+
+    if (boundary == BOUNDARY_REFLECT) {
+      pos[axis] = 2. * face - pos[axis];
+      vel[axis] = -vel[axis];
+    } elseif (boundary == BOUNDARY_PERIODIC) {
+      pos[axis] = pos[axis] + wrap;
+    } elseif (boundary == BOUNDARY_ABSORB) {
+      (*p).life = LIFE_ABSORBED;
+      return;
+    } else {
+      respawn(id);
+      return;
+    }
+  }
+
+  if (inside_electrode(pos)) {
+    (*p).life = LIFE_ELECTRODE;
+    return;
+  }
+  if (new_life < 0.) {
+    respawn(id);
+    return;
+  }
+  // `vel.w` carries the potential at the particle for the renderer, its
+  // potential energy is `charge * vel.w`.
+  (*p).pos = vec4<f32>(pos, (*p).pos.w);
+  (*p).vel = vec4<f32>(vel, get_potential(pos));
+  (*p).life = new_life;
+}
+
 [[stage(compute), workgroup_size(256, 1, 1)]]
 fn compute_field(
     [[builtin(global_invocation_id)]] global_id: vec3<u32>,
@@ -105,19 +547,159 @@ fn compute_field(
   let curr_pos = (*p).pos.xyz;
   let curr_vel = (*p).vel;
   let curr_life = (*p).life;
+  load_interaction(id);
 
   // let field = clamp(get_field(curr_pos), vec3<f32>(0.0001), vec3<f32>(20.));
-  let field = get_field(curr_pos); // , vec3<f32>(0.0001), vec3<f32>(20.));
+  let field = get_tracer_velocity(curr_pos) * (*p).pos.w; // , vec3<f32>(0.0001), vec3<f32>(20.));
   (*p).vel = vec4<f32>(field, curr_vel.w);
 }
 
-[[block]]
-struct Uniform {
-  v: f32;
-};
+// Gives parked particles another chance at the emitter budget.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn emit_parked(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  if (emit_params.seeding == SEEDING_EMITTERS
+      && emit_params.emitter_count > 0u
+      && particles.data[id].life == LIFE_PARKED) {
+    respawn(id);
+  }
+}
 
-[[group(1), binding(0)]]
-var<uniform> rand: Uniform;
+// Respawns the particles taken out with the lifetime `marker`, those
+// with emitters still within the budget.
+fn respawn_marked(id: u32, marker: f32) {
+  if (particles.data[id].life == marker) {
+    respawn(id);
+  }
+}
+
+// Used when the emitters the parked particles wait for are removed.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn respawn_parked(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  respawn_marked(global_id.x, LIFE_PARKED);
+}
+
+// Used when no face absorbs any more.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn respawn_absorbed(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  respawn_marked(global_id.x, LIFE_ABSORBED);
+}
+
+// Used when the electrodes are replaced.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn respawn_electrode_hits(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  respawn_marked(global_id.x, LIFE_ELECTRODE);
+}
+
+// Replaces every particle, used when the seeding changes.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn reseed(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  respawn(global_id.x);
+}
+
+// Explicit Euler along the velocity written by `compute_field`.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn integrate(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  let p = &particles.data[id];
+  let curr_pos = (*p).pos.xyz;
+  let curr_vel = (*p).vel.xyz;
+
+  advance(id, curr_pos + curr_vel * time.dt, curr_vel);
+}
+
+// Streamline tracing, `dx/dt = E(x)`.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn integrate_midpoint(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  load_interaction(id);
+  let x = particles.data[id].pos.xyz;
+  // Negative to trace the field line backwards.
+  let dt = time.dt * particles.data[id].pos.w;
+
+  let k1 = get_tracer_velocity(x);
+  let k2 = get_tracer_velocity(x + k1 * dt * 0.5);
+
+  advance(id, x + k2 * dt, k1);
+}
+
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn integrate_rk4(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  load_interaction(id);
+  let x = particles.data[id].pos.xyz;
+  // Negative to trace the field line backwards.
+  let dt = time.dt * particles.data[id].pos.w;
+
+  let k1 = get_tracer_velocity(x);
+  let k2 = get_tracer_velocity(x + k1 * dt * 0.5);
+  let k3 = get_tracer_velocity(x + k2 * dt * 0.5);
+  let k4 = get_tracer_velocity(x + k3 * dt);
+
+  advance(id, x + (k1 + 2. * k2 + 2. * k3 + k4) * dt / 6., k1);
+}
+
+// Massive charged particles, `dv/dt = q/m (E + v x B)` with the charge and
+// mass each particle was spawned with. The magnetic field is taken in tesla.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn integrate_verlet(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  load_interaction(id);
+  let x = particles.data[id].pos.xyz;
+  let v = particles.data[id].vel.xyz;
+  let qm = world_charge_to_mass(id);
+  let l = field_params.length_scale;
+  let dt = time.dt;
+
+  // The end-of-step velocity is not known yet, so the magnetic term of
+  // `new_a` uses the half-step velocity, which keeps the scheme explicit.
+  let a = qm * (get_field(x) + cross(v * l, get_magnetic_field(x)));
+  let new_x = x + v * dt + 0.5 * a * dt * dt;
+  let half_v = v + 0.5 * a * dt;
+  let new_a = qm * (get_field(new_x) + cross(half_v * l, get_magnetic_field(new_x)));
+
+  advance(id, new_x, v + 0.5 * (a + new_a) * dt);
+}
+
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn integrate_boris(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  load_interaction(id);
+  let x = particles.data[id].pos.xyz;
+  let v = particles.data[id].vel.xyz;
+  let qm = world_charge_to_mass(id);
+  let half_qm_dt = 0.5 * qm * time.dt;
+
+  let e = get_field(x);
+  let v_minus = v + half_qm_dt * e;
+  let t = half_qm_dt * field_params.length_scale * get_magnetic_field(x);
+  let s = 2. * t / (1. + dot(t, t));
+  let v_prime = v_minus + cross(v_minus, t);
+  let v_plus = v_minus + cross(v_prime, s);
+  let new_v = v_plus + half_qm_dt * e;
+
+  advance(id, x + new_v * time.dt, new_v);
+}
 
 [[stage(compute), workgroup_size(256, 1, 1)]]
 fn fill(
@@ -126,5 +708,36 @@ fn fill(
   let id = global_id.x;
   let p = &particles.data[id];
 
-  (*p) = generate_particle(id);
+  (*p) = generate_particle(particle_seed(id));
+}
+
+[[block]]
+struct ProbeParams {
+  point_count: u32;
+};
+
+// Probe points in `xyz`, see `FieldProbe` in `probe.rs`.
+[[block]]
+struct ProbePoints {
+  data: [[stride(16)]] array<vec4<f32>>;
+};
+
+[[group(3), binding(0)]]
+var<uniform> probe_params: ProbeParams;
+[[group(3), binding(1)]]
+var<storage, read> probe_points: ProbePoints;
+// E in V/m in `xyz` and the potential in volts in `w`.
+[[group(3), binding(2)]]
+var<storage, read_write> probe_results: ProbePoints;
+
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn probe_field(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+  let id = global_id.x;
+  if (id >= probe_params.point_count) {
+    return;
+  }
+  let p = probe_points.data[id].xyz;
+  probe_results.data[id] = vec4<f32>(get_field(p), get_potential(p));
 }
@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+/// Fixed-timestep accumulator, so the simulation advances by the same
+/// amount of simulated time per wall-clock second regardless of frame rate.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    /// Wall-clock duration of one simulation step.
+    pub step: Duration,
+    /// Simulated time per wall-clock second.
+    pub time_scale: f32,
+    /// Upper bound of steps taken in a single frame. Whatever is left in the
+    /// accumulator after that is dropped instead of snowballing.
+    pub max_substeps: u32,
+    accumulator: Duration,
+    last_update: Instant,
+}
+
+impl FixedTimestep {
+    pub fn new(step: Duration, time_scale: f32, max_substeps: u32) -> Self {
+        Self {
+            step,
+            time_scale,
+            max_substeps,
+            accumulator: Duration::ZERO,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Simulated time covered by one step.
+    pub fn dt(&self) -> f32 {
+        self.step.as_secs_f32() * self.time_scale
+    }
+
+    /// Accumulates the time elapsed since the last call and returns the number
+    /// of fixed steps to run this frame.
+    pub fn advance(&mut self) -> u32 {
+        let now = Instant::now();
+        self.accumulator += now - self.last_update;
+        self.last_update = now;
+
+        let mut substeps = 0;
+        while self.accumulator >= self.step && substeps < self.max_substeps {
+            self.accumulator -= self.step;
+            substeps += 1;
+        }
+        if substeps == self.max_substeps {
+            self.accumulator = self.accumulator.min(self.step);
+        }
+        substeps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestep_after(elapsed: Duration) -> FixedTimestep {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(100), 2., 4);
+        timestep.last_update -= elapsed;
+        timestep
+    }
+
+    #[test]
+    fn dt() {
+        assert!((timestep_after(Duration::ZERO).dt() - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn whole_steps() {
+        let mut timestep = timestep_after(Duration::from_millis(250));
+        assert_eq!(timestep.advance(), 2);
+        // The remaining 50 ms are carried over.
+        timestep.last_update -= Duration::from_millis(50);
+        assert_eq!(timestep.advance(), 1);
+    }
+
+    #[test]
+    fn substeps_are_clamped() {
+        let mut timestep = timestep_after(Duration::from_secs(1));
+        assert_eq!(timestep.advance(), 4);
+        // At most one step is left in the accumulator.
+        assert!(timestep.advance() <= 1);
+    }
+}